@@ -0,0 +1,435 @@
+//! The syntax tree as JSON, for tools that work on Lox programs.
+//!
+//! ```text
+//! program := {"version": 1, "statements": [stmt*]}
+//! node    := {"kind": string, "span": span, field*}
+//! token   := {"type": string, "lexeme": string, "line": number,
+//!             "column": number, "span": span}
+//! span    := [start, end]
+//! ```
+//!
+//! Spans are half-open byte offsets into the source; lines and columns
+//! count from 1. `depth` is how many scopes out a variable was declared,
+//! or null for globals. Missing optional children are null.
+//!
+//! ```text
+//! Expression  expression
+//! Print       expression
+//! Var         name:token  initializer:expr|null
+//! Block       statements:[stmt]
+//! If          condition:expr  then:stmt  else:stmt|null
+//! While       condition:expr  body:stmt  increment:expr|null
+//! Function    name:token  params:[token]  body:[stmt]
+//! Return      keyword:token  value:expr|null
+//! Class       name:token  superclass:Variable|null  methods:[Function]
+//! Break       keyword:token
+//! Continue    keyword:token
+//!
+//! Binary      left:expr  operator:token  right:expr
+//! Logical     left:expr  operator:token  right:expr
+//! Grouping    expression:expr
+//! Literal     value:null|bool|number|string
+//! Unary       operator:token  right:expr
+//! Variable    name:token  depth
+//! Ternary     condition:expr  then:expr  else:expr
+//! Assign      name:token  value:expr  depth
+//! Call        callee:expr  paren:token  arguments:[expr]
+//! Get         object:expr  name:token
+//! Set         object:expr  name:token  value:expr
+//! This        keyword:token  depth
+//! Super       keyword:token  method:token  depth
+//! List        items:[expr]
+//! Map         entries:[{key:expr, colon:token, value:expr}]
+//! Index       object:expr  bracket:token  index:expr
+//! SetIndex    object:expr  bracket:token  index:expr  value:expr
+//! ```
+//!
+//! `read_program` accepts the same schema with only kinds, children and
+//! token lexemes required, so programs can be generated without source.
+//!
+//! Fields may be added without a new version; renaming or removing one,
+//! or changing what it means, bumps `SCHEMA_VERSION`.
+
+use crate::{
+    json::Json,
+    parser::{Expr, FunctionDecl, Stmt},
+    scanner::Scanner,
+    token::{Span, Token, TokenType},
+    value::Value,
+};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// `{"version": 1, "statements": [...]}`.
+pub fn program(statements: &[Stmt]) -> Json {
+    Json::object([
+        ("version", Json::Number(SCHEMA_VERSION as f64)),
+        ("statements", Json::Array(statements.iter().map(stmt).collect())),
+    ])
+}
+
+/// A node: its kind and span, then its fields.
+fn node<const N: usize>(kind: &str, span: Span, fields: [(&str, Json); N]) -> Json {
+    let mut node = vec![
+        ("kind".to_string(), Json::String(kind.to_string())),
+        ("span".to_string(), span_json(span)),
+    ];
+    node.extend(fields.into_iter().map(|(key, value)| (key.to_string(), value)));
+    Json::Object(node)
+}
+
+fn stmt(stmt: &Stmt) -> Json {
+    let span = stmt.span();
+    match stmt {
+        Stmt::Expression(expression) => node("Expression", span, [("expression", expr(expression))]),
+        Stmt::Print(expression, _) => node("Print", span, [("expression", expr(expression))]),
+        Stmt::Let(name, initializer) => node("Var", span, [
+            ("name", token(name)),
+            ("initializer", initializer.as_deref().map_or(Json::Null, expr)),
+        ]),
+        Stmt::Block(statements, _) => node("Block", span, [("statements", stmts(statements))]),
+        Stmt::If(condition, then, else_, _) => node("If", span, [
+            ("condition", expr(condition)),
+            ("then", self::stmt(then)),
+            ("else", else_.as_ref().as_ref().map_or(Json::Null, self::stmt)),
+        ]),
+        Stmt::While(condition, body, increment, _) => node("While", span, [
+            ("condition", expr(condition)),
+            ("body", self::stmt(body)),
+            ("increment", increment.as_deref().map_or(Json::Null, expr)),
+        ]),
+        Stmt::Function(declaration) => function(declaration),
+        Stmt::Return(keyword, value) => node("Return", span, [
+            ("keyword", token(keyword)),
+            ("value", value.as_deref().map_or(Json::Null, expr)),
+        ]),
+        Stmt::Class(name, superclass, methods) => node("Class", span, [
+            ("name", token(name)),
+            ("superclass", superclass.as_ref().map_or(Json::Null, expr)),
+            ("methods", Json::Array(methods.iter().map(function).collect())),
+        ]),
+        Stmt::Break(keyword) => node("Break", span, [("keyword", token(keyword))]),
+        Stmt::Continue(keyword) => node("Continue", span, [("keyword", token(keyword))]),
+    }
+}
+
+fn stmts(statements: &[Stmt]) -> Json {
+    Json::Array(statements.iter().map(stmt).collect())
+}
+
+fn function(declaration: &FunctionDecl) -> Json {
+    node("Function", declaration.name.span, [
+        ("name", token(&declaration.name)),
+        ("params", Json::Array(declaration.params.iter().map(token).collect())),
+        ("body", stmts(&declaration.body)),
+    ])
+}
+
+fn expr(expr: &Expr) -> Json {
+    let span = expr.span();
+    match expr {
+        Expr::Binary(left, operator, right) => node("Binary", span, [
+            ("left", self::expr(left)),
+            ("operator", token(operator)),
+            ("right", self::expr(right)),
+        ]),
+        Expr::Logical(left, operator, right) => node("Logical", span, [
+            ("left", self::expr(left)),
+            ("operator", token(operator)),
+            ("right", self::expr(right)),
+        ]),
+        Expr::Grouping(expression, _) => node("Grouping", span, [("expression", self::expr(expression))]),
+        Expr::Literal(value, _) => node("Literal", span, [("value", literal(value))]),
+        Expr::Unary(operator, right) => node("Unary", span, [
+            ("operator", token(operator)),
+            ("right", self::expr(right)),
+        ]),
+        Expr::Variable(name, depth) => node("Variable", span, [
+            ("name", token(name)),
+            ("depth", depth_json(*depth)),
+        ]),
+        Expr::Ternary(condition, then, else_) => node("Ternary", span, [
+            ("condition", self::expr(condition)),
+            ("then", self::expr(then)),
+            ("else", self::expr(else_)),
+        ]),
+        Expr::Assign(name, value, depth) => node("Assign", span, [
+            ("name", token(name)),
+            ("value", self::expr(value)),
+            ("depth", depth_json(*depth)),
+        ]),
+        Expr::Call(callee, paren, arguments) => node("Call", span, [
+            ("callee", self::expr(callee)),
+            ("paren", token(paren)),
+            ("arguments", Json::Array(arguments.iter().map(self::expr).collect())),
+        ]),
+        Expr::Get(object, name) => node("Get", span, [
+            ("object", self::expr(object)),
+            ("name", token(name)),
+        ]),
+        Expr::Set(object, name, value) => node("Set", span, [
+            ("object", self::expr(object)),
+            ("name", token(name)),
+            ("value", self::expr(value)),
+        ]),
+        Expr::This(keyword, depth) => node("This", span, [
+            ("keyword", token(keyword)),
+            ("depth", depth_json(*depth)),
+        ]),
+        Expr::Super(keyword, method, depth) => node("Super", span, [
+            ("keyword", token(keyword)),
+            ("method", token(method)),
+            ("depth", depth_json(*depth)),
+        ]),
+        Expr::List(items, _) => node("List", span, [
+            ("items", Json::Array(items.iter().map(self::expr).collect())),
+        ]),
+        Expr::Map(entries, _) => node("Map", span, [
+            ("entries", Json::Array(entries.iter().map(|(key, colon, value)| Json::object([
+                ("key", self::expr(key)),
+                ("colon", token(colon)),
+                ("value", self::expr(value)),
+            ])).collect())),
+        ]),
+        Expr::Index(object, bracket, index) => node("Index", span, [
+            ("object", self::expr(object)),
+            ("bracket", token(bracket)),
+            ("index", self::expr(index)),
+        ]),
+        Expr::SetIndex(object, bracket, index, value) => node("SetIndex", span, [
+            ("object", self::expr(object)),
+            ("bracket", token(bracket)),
+            ("index", self::expr(index)),
+            ("value", self::expr(value)),
+        ]),
+    }
+}
+
+/// Literals in source are only ever nil, booleans, numbers or strings.
+fn literal(value: &Value) -> Json {
+    match value {
+        Value::Bool(b) => Json::Bool(*b),
+        Value::Number(n) => Json::Number(*n),
+        Value::String(s) => Json::String(s.clone()),
+        _ => Json::Null,
+    }
+}
+
+fn token(token: &Token) -> Json {
+    Json::object([
+        ("type", Json::String(format!("{:?}", token.token_type))),
+        ("lexeme", Json::String(token.lexeme.clone())),
+        ("line", Json::Number(token.line as f64)),
+        ("column", Json::Number(token.column as f64)),
+        ("span", span_json(token.span)),
+    ])
+}
+
+fn span_json(span: Span) -> Json {
+    Json::Array(vec![Json::Number(span.start as f64), Json::Number(span.end as f64)])
+}
+
+fn depth_json(depth: Option<usize>) -> Json {
+    depth.map_or(Json::Null, |depth| Json::Number(depth as f64))
+}
+
+/// Rebuilds the statements of a program written in this schema. Only
+/// `kind`, the children and token lexemes are required: spans, lines and
+/// columns default to zero, token types are worked out from the lexeme,
+/// and depths are ignored since the resolver works them out again.
+pub fn read_program(json: &Json) -> Result<Vec<Stmt>, String> {
+    match json.get("version") {
+        Some(Json::Number(version)) if *version == SCHEMA_VERSION as f64 => {},
+        Some(version) => return Err(format!("unsupported schema version {}", version)),
+        None => return Err("missing schema version".to_string()),
+    }
+
+    array(json, "statements")?.iter().map(read_stmt).collect()
+}
+
+fn read_stmt(json: &Json) -> Result<Stmt, String> {
+    let span = read_span(json)?;
+    let stmt = match kind(json)? {
+        "Expression" => Stmt::Expression(Box::new(read_expr(field(json, "expression")?)?)),
+        "Print" => Stmt::Print(Box::new(read_expr(field(json, "expression")?)?), span),
+        "Var" => Stmt::Let(
+            read_token(field(json, "name")?)?,
+            optional(json, "initializer").map(read_expr).transpose()?.map(Box::new),
+        ),
+        "Block" => Stmt::Block(read_stmts(json, "statements")?, span),
+        "If" => Stmt::If(
+            read_expr(field(json, "condition")?)?,
+            Box::new(read_stmt(field(json, "then")?)?),
+            Box::new(optional(json, "else").map(read_stmt).transpose()?),
+            span,
+        ),
+        "While" => Stmt::While(
+            read_expr(field(json, "condition")?)?,
+            Box::new(read_stmt(field(json, "body")?)?),
+            optional(json, "increment").map(read_expr).transpose()?.map(Box::new),
+            span,
+        ),
+        "Function" => Stmt::Function(read_function(json)?),
+        "Return" => Stmt::Return(
+            read_token(field(json, "keyword")?)?,
+            optional(json, "value").map(read_expr).transpose()?.map(Box::new),
+        ),
+        "Class" => {
+            let superclass = optional(json, "superclass").map(read_expr).transpose()?;
+            if superclass.as_ref().is_some_and(|superclass| !matches!(superclass, Expr::Variable(..))) {
+                return Err("a Class superclass must be a Variable".to_string());
+            }
+            Stmt::Class(
+                read_token(field(json, "name")?)?,
+                superclass,
+                array(json, "methods")?.iter().map(read_function).collect::<Result<_, _>>()?,
+            )
+        },
+        "Break" => Stmt::Break(read_token(field(json, "keyword")?)?),
+        "Continue" => Stmt::Continue(read_token(field(json, "keyword")?)?),
+        other => return Err(format!("unknown statement kind '{}'", other)),
+    };
+    Ok(stmt)
+}
+
+fn read_stmts(json: &Json, key: &str) -> Result<Vec<Stmt>, String> {
+    array(json, key)?.iter().map(read_stmt).collect()
+}
+
+fn read_function(json: &Json) -> Result<FunctionDecl, String> {
+    if kind(json)? != "Function" {
+        return Err(format!("expected a Function, found '{}'", kind(json)?));
+    }
+
+    Ok(FunctionDecl {
+        name: read_token(field(json, "name")?)?,
+        params: array(json, "params")?.iter().map(read_token).collect::<Result<_, _>>()?,
+        body: read_stmts(json, "body")?,
+    })
+}
+
+fn read_expr(json: &Json) -> Result<Expr, String> {
+    let expr = |key| read_expr(field(json, key)?).map(Box::new);
+    let token = |key| read_token(field(json, key)?);
+
+    let expr = match kind(json)? {
+        "Binary" => Expr::Binary(expr("left")?, read_operator(json, BINARY_OPERATORS)?, expr("right")?),
+        "Logical" => Expr::Logical(expr("left")?, read_operator(json, LOGICAL_OPERATORS)?, expr("right")?),
+        "Grouping" => Expr::Grouping(expr("expression")?, read_span(json)?),
+        "Literal" => {
+            let value = match field(json, "value")? {
+                Json::Null => Value::Nil,
+                Json::Bool(b) => Value::Bool(*b),
+                Json::Number(n) => Value::Number(*n),
+                Json::String(s) => Value::String(s.clone()),
+                _ => return Err("a Literal value must be null, a boolean, a number or a string".to_string()),
+            };
+            Expr::Literal(value, read_span(json)?)
+        },
+        "Unary" => Expr::Unary(read_operator(json, UNARY_OPERATORS)?, expr("right")?),
+        "Variable" => Expr::Variable(token("name")?, None),
+        "Ternary" => Expr::Ternary(expr("condition")?, expr("then")?, expr("else")?),
+        "Assign" => Expr::Assign(token("name")?, expr("value")?, None),
+        "Call" => Expr::Call(
+            expr("callee")?,
+            token("paren")?,
+            array(json, "arguments")?.iter().map(read_expr).collect::<Result<_, _>>()?,
+        ),
+        "Get" => Expr::Get(expr("object")?, token("name")?),
+        "Set" => Expr::Set(expr("object")?, token("name")?, expr("value")?),
+        "This" => Expr::This(token("keyword")?, None),
+        "Super" => Expr::Super(token("keyword")?, token("method")?, None),
+        "List" => Expr::List(
+            array(json, "items")?.iter().map(read_expr).collect::<Result<_, _>>()?,
+            read_span(json)?,
+        ),
+        "Map" => Expr::Map(
+            array(json, "entries")?.iter().map(read_entry).collect::<Result<_, _>>()?,
+            read_span(json)?,
+        ),
+        "Index" => Expr::Index(expr("object")?, token("bracket")?, expr("index")?),
+        "SetIndex" => Expr::SetIndex(expr("object")?, token("bracket")?, expr("index")?, expr("value")?),
+        other => return Err(format!("unknown expression kind '{}'", other)),
+    };
+    Ok(expr)
+}
+
+fn read_entry(json: &Json) -> Result<(Expr, Token, Expr), String> {
+    Ok((read_expr(field(json, "key")?)?, read_token(field(json, "colon")?)?, read_expr(field(json, "value")?)?))
+}
+
+const BINARY_OPERATORS: &[TokenType] = &[
+    TokenType::BangEqual, TokenType::EqualEqual, TokenType::Greater, TokenType::GreaterEqual,
+    TokenType::Less, TokenType::LessEqual, TokenType::Minus, TokenType::Plus, TokenType::Slash, TokenType::Star,
+];
+const LOGICAL_OPERATORS: &[TokenType] = &[TokenType::And, TokenType::Or];
+const UNARY_OPERATORS: &[TokenType] = &[TokenType::Bang, TokenType::Minus];
+
+/// The `operator` of a node, which the parser would only ever have given
+/// one of `allowed`.
+fn read_operator(json: &Json, allowed: &[TokenType]) -> Result<Token, String> {
+    let operator = read_token(field(json, "operator")?)?;
+    if !allowed.contains(&operator.token_type) {
+        return Err(format!("'{}' is not a {} operator", operator.lexeme, kind(json)?));
+    }
+    Ok(operator)
+}
+
+fn read_token(json: &Json) -> Result<Token, String> {
+    let Some(Json::String(lexeme)) = json.get("lexeme") else {
+        return Err("a token needs a string 'lexeme'".to_string());
+    };
+
+    // Scanning the lexeme gives its type, and checks it is one token.
+    let mut scanner = Scanner::new(lexeme.clone());
+    let token_type = match scanner.scan_tokens().map(|tokens| tokens.as_slice()) {
+        Ok([token, _eof]) if token.lexeme == *lexeme => token.token_type,
+        _ => return Err(format!("'{}' is not a single token", lexeme)),
+    };
+
+    let line = json.get("line").map_or(Ok(1), number)?;
+    if line == 0 {
+        return Err("a token's 'line' counts from 1".to_string());
+    }
+    let column = json.get("column").map_or(Ok(0), number)?;
+    Ok(Token::new(token_type, lexeme.clone(), line).at(column, read_span(json)?))
+}
+
+fn read_span(json: &Json) -> Result<Span, String> {
+    match json.get("span") {
+        None | Some(Json::Null) => Ok(Span::default()),
+        Some(Json::Array(bounds)) if bounds.len() == 2 => Ok(Span::new(number(&bounds[0])?, number(&bounds[1])?)),
+        Some(_) => Err("a span must be [start, end]".to_string()),
+    }
+}
+
+fn number(json: &Json) -> Result<usize, String> {
+    match json {
+        Json::Number(n) if n.fract() == 0.0 && *n >= 0.0 => Ok(*n as usize),
+        Json::Number(n) => Err(format!("expected a whole number, found {}", n)),
+        _ => Err(format!("expected a whole number, found {}", json.type_name())),
+    }
+}
+
+fn kind(json: &Json) -> Result<&str, String> {
+    match json.get("kind") {
+        Some(Json::String(kind)) => Ok(kind),
+        _ => Err(format!("expected a node with a 'kind', found {}", json.type_name())),
+    }
+}
+
+fn field<'a>(json: &'a Json, key: &str) -> Result<&'a Json, String> {
+    json.get(key).ok_or_else(|| format!("{} is missing '{}'", kind(json).unwrap_or("node"), key))
+}
+
+/// A child that may be left out or null.
+fn optional<'a>(json: &'a Json, key: &str) -> Option<&'a Json> {
+    json.get(key).filter(|value| **value != Json::Null)
+}
+
+fn array<'a>(json: &'a Json, key: &str) -> Result<&'a [Json], String> {
+    match field(json, key)? {
+        Json::Array(items) => Ok(items),
+        _ => Err(format!("'{}' of {} must be an array", key, kind(json).unwrap_or("node"))),
+    }
+}
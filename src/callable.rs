@@ -0,0 +1,100 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use crate::{
+    class::LoxInstance,
+    environment::Environment,
+    error::LoxError,
+    interpreter::{Interpreter, Unwind},
+    parser::FunctionDecl,
+    token::Token,
+    value::Value,
+};
+
+/// Anything that can sit on the left of a call expression.
+pub trait LoxCallable {
+    fn arity(&self) -> usize;
+    fn call(self: Rc<Self>, interpreter: &mut Interpreter, paren: &Token, arguments: Vec<Value>) -> Result<Value, LoxError>;
+}
+
+pub struct LoxFunction {
+    pub declaration: Rc<FunctionDecl>,
+    /// The environment the function was declared in.
+    closure: Rc<RefCell<Environment>>,
+    is_initializer: bool,
+}
+
+impl LoxFunction {
+    pub fn new(
+        declaration: Rc<FunctionDecl>,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool
+    ) -> Self {
+        Self { declaration, closure, is_initializer }
+    }
+
+    /// A copy of this method whose `this` is `instance`.
+    pub fn bind(&self, instance: Rc<RefCell<LoxInstance>>) -> LoxFunction {
+        let mut env = Environment::with_enclosing(Rc::clone(&self.closure));
+        env.define("this".to_string(), Value::Instance(instance));
+
+        LoxFunction::new(
+            Rc::clone(&self.declaration),
+            Rc::new(RefCell::new(env)),
+            self.is_initializer
+        )
+    }
+
+    fn this(&self) -> Value {
+        self.closure.borrow().get_local("this").unwrap_or(Value::Nil)
+    }
+}
+
+impl fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The closure can refer back to this function, so leave it out.
+        write!(f, "<fn {}>", self.declaration.name.lexeme)
+    }
+}
+
+impl LoxCallable for LoxFunction {
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    fn call(self: Rc<Self>, interpreter: &mut Interpreter, _paren: &Token, arguments: Vec<Value>) -> Result<Value, LoxError> {
+        let mut env = Environment::with_enclosing(Rc::clone(&self.closure));
+        for (param, arg) in self.declaration.params.iter().zip(arguments) {
+            env.define(param.lexeme.clone(), arg);
+        }
+
+        let result = match interpreter.execute_block(&self.declaration.body, env) {
+            Ok(()) => Value::Nil,
+            Err(Unwind::Return(value)) => value,
+            Err(Unwind::Error(error)) => return Err(error),
+            Err(Unwind::Break | Unwind::Continue) => unreachable!("the resolver rejects jumps outside loops"),
+        };
+
+        if self.is_initializer {
+            return Ok(self.this());
+        }
+        Ok(result)
+    }
+}
+
+/// A function implemented in Rust and exposed to Lox as a global.
+#[derive(Debug)]
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: fn(&[Value]) -> Result<Value, String>,
+}
+
+impl LoxCallable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(self: Rc<Self>, _interpreter: &mut Interpreter, paren: &Token, arguments: Vec<Value>) -> Result<Value, LoxError> {
+        (self.function)(&arguments).map_err(|msg| LoxError::runtime(paren, msg))
+    }
+}
@@ -0,0 +1,129 @@
+use std::rc::Rc;
+
+use crate::token::Span;
+
+/// One instruction of the virtual machine. Operands follow the opcode in
+/// the byte stream; constant indices and jump offsets take two bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum OpCode {
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal,
+    SetLocal,
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    GetUpvalue,
+    SetUpvalue,
+    GetProperty,
+    SetProperty,
+    GetSuper,
+    Equal,
+    Greater,
+    Less,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    Call,
+    Closure,
+    CloseUpvalue,
+    Return,
+    Class,
+    /// Pops its two-byte count of items and pushes them as a list.
+    BuildList,
+    GetIndex,
+    SetIndex,
+    /// Pops its two-byte count of key and value pairs and pushes them as a
+    /// map.
+    BuildMap,
+}
+
+impl OpCode {
+    const ALL: [OpCode; 37] = [
+        OpCode::Constant, OpCode::Nil, OpCode::True, OpCode::False,
+        OpCode::Pop, OpCode::GetLocal, OpCode::SetLocal, OpCode::GetGlobal,
+        OpCode::DefineGlobal, OpCode::SetGlobal, OpCode::GetUpvalue,
+        OpCode::SetUpvalue, OpCode::GetProperty, OpCode::SetProperty,
+        OpCode::GetSuper, OpCode::Equal, OpCode::Greater, OpCode::Less,
+        OpCode::Add, OpCode::Subtract, OpCode::Multiply, OpCode::Divide,
+        OpCode::Not, OpCode::Negate, OpCode::Print, OpCode::Jump,
+        OpCode::JumpIfFalse, OpCode::Loop, OpCode::Call, OpCode::Closure,
+        OpCode::CloseUpvalue, OpCode::Return, OpCode::Class, OpCode::BuildList,
+        OpCode::GetIndex, OpCode::SetIndex, OpCode::BuildMap,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        Self::ALL.get(byte as usize).copied()
+    }
+}
+
+/// A value known at compile time, stored in a chunk's constant pool.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Number(f64),
+    String(String),
+    Function(Rc<FunctionProto>),
+}
+
+/// A compiled function body: its bytecode plus what the VM needs to call it.
+#[derive(Debug, PartialEq)]
+pub struct FunctionProto {
+    pub name: String,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    /// The source line of every byte in `code`.
+    pub lines: Vec<usize>,
+    /// The source span runtime errors point at, for every byte in `code`.
+    /// An opcode's span is what the instruction itself reports errors at;
+    /// its operand bytes may carry a narrower one, such as the property name
+    /// of a `GetProperty`. Empty for chunks loaded from a `.loxc` file.
+    pub spans: Vec<Span>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, byte: u8, line: usize, span: Span) {
+        self.code.push(byte);
+        self.lines.push(line);
+        self.spans.push(span);
+    }
+
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        let same = |c: &Constant| match (c, &constant) {
+            // `0.0 == -0.0`, but they print differently.
+            (Constant::Number(a), Constant::Number(b)) => a.to_bits() == b.to_bits(),
+            (a, b) => a == b,
+        };
+        if let Some(index) = self.constants.iter().position(same) {
+            return index;
+        }
+
+        self.constants.push(constant);
+        self.constants.len() - 1
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+}
@@ -0,0 +1,97 @@
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+
+use crate::{
+    callable::LoxCallable,
+    error::LoxError,
+    interpreter::Interpreter,
+    token::Token,
+    value::Value,
+};
+
+/// A class, shared by both backends. Methods are whatever callable value
+/// the backend uses for them: `Value::Function` for the tree-walking
+/// interpreter, `Value::Closure` for the virtual machine.
+#[derive(Debug)]
+pub struct LoxClass {
+    pub name: String,
+    superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<String, Value>,
+}
+
+impl LoxClass {
+    pub fn new(
+        name: String,
+        superclass: Option<Rc<LoxClass>>,
+        methods: HashMap<String, Value>
+    ) -> Self {
+        Self { name, superclass, methods }
+    }
+
+    /// Looks `name` up on this class, then along the superclass chain.
+    pub fn find_method(&self, name: &str) -> Option<Value> {
+        if let Some(method) = self.methods.get(name) {
+            return Some(method.clone());
+        }
+
+        self.superclass.as_ref()?.find_method(name)
+    }
+}
+
+impl LoxCallable for LoxClass {
+    fn arity(&self) -> usize {
+        match self.find_method("init") {
+            Some(Value::Function(initializer)) => initializer.arity(),
+            _ => 0,
+        }
+    }
+
+    fn call(self: Rc<Self>, interpreter: &mut Interpreter, paren: &Token, arguments: Vec<Value>) -> Result<Value, LoxError> {
+        let instance = Rc::new(RefCell::new(LoxInstance::new(Rc::clone(&self))));
+
+        if let Some(Value::Function(initializer)) = self.find_method("init") {
+            Rc::new(initializer.bind(Rc::clone(&instance))).call(interpreter, paren, arguments)?;
+        }
+
+        Ok(Value::Instance(instance))
+    }
+}
+
+pub struct LoxInstance {
+    pub class: Rc<LoxClass>,
+    fields: HashMap<String, Value>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> Self {
+        Self { class, fields: HashMap::new() }
+    }
+
+    pub fn field(&self, name: &str) -> Option<Value> {
+        self.fields.get(name).cloned()
+    }
+
+    /// Looks up a field first, then a method bound to `instance`.
+    pub fn get(instance: &Rc<RefCell<LoxInstance>>, name: &Token) -> Result<Value, LoxError> {
+        if let Some(value) = instance.borrow().field(&name.lexeme) {
+            return Ok(value);
+        }
+
+        let method = instance.borrow().class.find_method(&name.lexeme);
+        if let Some(Value::Function(method)) = method {
+            return Ok(Value::Function(Rc::new(method.bind(Rc::clone(instance)))));
+        }
+
+        Err(LoxError::runtime(name, format!("Undefined property '{}'.", name.lexeme)))
+    }
+
+    pub fn set(&mut self, name: &str, value: Value) {
+        self.fields.insert(name.to_string(), value);
+    }
+}
+
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Fields can point back at this instance, so only show the class.
+        write!(f, "<{} instance>", self.class.name)
+    }
+}
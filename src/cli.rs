@@ -0,0 +1,189 @@
+use std::io::{self, Read};
+
+use crate::Backend;
+
+pub const USAGE: &str = "\
+Usage: rlox [options] [script | -e <code> | -] [args...]
+       rlox [options] <command> [script | -e <code> | -]
+
+Commands:
+  run <script>      Run a script (the default when a script is given)
+  run-ast <json>    Run a syntax tree written as JSON (see `ast --format json`)
+  repl              Start an interactive session (the default otherwise)
+  tokens <script>   Print the tokens of a script
+  ast <script>      Print the syntax tree of a script
+  check <script>    Report errors without running the script
+  fmt <script>      Format a script in place, or print it for -e and -
+
+Options:
+  -e <code>                    Use <code> as the script
+  -                            Read the script from stdin
+  --backend tree|vm            Engine that runs the program (default: tree)
+  --compile <out.loxc>         Write bytecode instead of running (run only)
+  --format json|sexpr|debug    Syntax tree format (ast only, default: sexpr)
+  -o, --output <file>          Write the syntax tree to <file> (ast only)
+  --check                      Fail if formatting would change the script (fmt only)
+  -h, --help                   Print this help
+  -V, --version                Print the version
+
+Arguments after the script are passed to it in the `args` list.
+
+Exit codes: 0 success, 64 bad usage, 65 error in the script,
+66 script not readable, 70 runtime error, 74 output not writable.";
+
+/// Where a script's text comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Script {
+    File(String),
+    /// `-e <code>`.
+    Inline(String),
+    /// `-`.
+    Stdin,
+}
+
+impl Script {
+    /// The name diagnostics give the script.
+    pub fn name(&self) -> &str {
+        match self {
+            Script::File(path) => path,
+            Script::Inline(_) => "<-e>",
+            Script::Stdin => "<stdin>",
+        }
+    }
+
+    pub fn read(&self) -> io::Result<Vec<u8>> {
+        match self {
+            Script::File(path) => std::fs::read(path),
+            Script::Inline(code) => Ok(code.clone().into_bytes()),
+            Script::Stdin => {
+                let mut bytes = Vec::new();
+                io::stdin().read_to_end(&mut bytes)?;
+                Ok(bytes)
+            },
+        }
+    }
+}
+
+/// How `rlox ast` prints the tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AstFormat {
+    Json,
+    Sexpr,
+    /// Rust's `{:#?}` output.
+    Debug,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run(Script),
+    RunAst(Script),
+    Repl,
+    Tokens(Script),
+    Ast { script: Script, format: AstFormat, output: Option<String> },
+    Check(Script),
+    /// With `check`, only report whether the script is formatted.
+    Fmt { script: Script, check: bool },
+    Help,
+    Version,
+}
+
+/// Everything the command line asked for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cli {
+    pub backend: Backend,
+    /// Where `--compile` writes bytecode.
+    pub compile: Option<String>,
+    pub command: Command,
+    /// What follows the script when running it.
+    pub args: Vec<String>,
+}
+
+const COMMANDS: [&str; 7] = ["run", "run-ast", "repl", "tokens", "ast", "check", "fmt"];
+
+/// Parses the arguments that follow the program name.
+pub fn parse(args: &[String]) -> Result<Cli, String> {
+    let mut backend = Backend::Interpreter;
+    let mut compile = None;
+    let mut format = None;
+    let mut output = None;
+    let mut inline = None;
+    let mut check = false;
+    let mut command: Option<&str> = None;
+    let mut positional: Vec<&str> = Vec::new();
+    let mut script_args: Vec<String> = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        // Once a script to run is known, the rest belongs to it.
+        let runs = matches!(command, None | Some("run") | Some("run-ast"));
+        if runs && (inline.is_some() || !positional.is_empty()) {
+            script_args.push(arg.clone());
+            script_args.extend(args.cloned());
+            break;
+        }
+
+        let mut value = || args.next().ok_or_else(|| format!("'{}' needs a value", arg));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Cli { backend, compile, command: Command::Help, args: Vec::new() }),
+            "-V" | "--version" => return Ok(Cli { backend, compile, command: Command::Version, args: Vec::new() }),
+            "--backend" => backend = match value()?.as_str() {
+                "tree" => Backend::Interpreter,
+                "vm" => Backend::Vm,
+                other => return Err(format!("unknown backend '{}', expected tree or vm", other)),
+            },
+            "--compile" => compile = Some(value()?.clone()),
+            "--format" => format = Some(match value()?.as_str() {
+                "json" => AstFormat::Json,
+                "sexpr" => AstFormat::Sexpr,
+                "debug" => AstFormat::Debug,
+                other => return Err(format!("unknown format '{}', expected json, sexpr or debug", other)),
+            }),
+            "-o" | "--output" => output = Some(value()?.clone()),
+            "-e" => inline = Some(value()?.clone()),
+            "--check" => check = true,
+            "-" => positional.push(arg),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ if command.is_none() && positional.is_empty() && COMMANDS.contains(&arg.as_str()) => {
+                command = Some(arg);
+            },
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let script = match (inline, positional.next()) {
+        (Some(code), None) => Some(Script::Inline(code)),
+        (None, Some("-")) => Some(Script::Stdin),
+        (None, Some(path)) => Some(Script::File(path.to_string())),
+        (None, None) => None,
+        (Some(_), Some(path)) => return Err(format!("unexpected argument '{}' after -e", path)),
+    };
+    if let Some(extra) = positional.next() {
+        return Err(format!("unexpected argument '{}'", extra));
+    }
+
+    let command = command.unwrap_or(if script.is_some() { "run" } else { "repl" });
+    if compile.is_some() && command != "run" {
+        return Err("--compile only applies to 'run'".to_string());
+    }
+    if (format.is_some() || output.is_some()) && command != "ast" {
+        return Err("--format and --output only apply to 'ast'".to_string());
+    }
+    if check && command != "fmt" {
+        return Err("--check only applies to 'fmt'".to_string());
+    }
+
+    let command = match (command, script) {
+        ("repl", None) => Command::Repl,
+        ("repl", Some(_)) => return Err("'repl' doesn't take a script".to_string()),
+        (command, None) => return Err(format!("'{}' needs a script", command)),
+        ("run", Some(script)) => Command::Run(script),
+        ("run-ast", Some(script)) => Command::RunAst(script),
+        ("tokens", Some(script)) => Command::Tokens(script),
+        ("ast", Some(script)) => Command::Ast { script, format: format.unwrap_or(AstFormat::Sexpr), output },
+        ("check", Some(script)) => Command::Check(script),
+        ("fmt", Some(script)) => Command::Fmt { script, check },
+        (command, Some(_)) => unreachable!("unknown command '{}'", command),
+    };
+    Ok(Cli { backend, compile, command, args: script_args })
+}
@@ -0,0 +1,708 @@
+use std::rc::Rc;
+
+use crate::{
+    chunk::{Chunk, Constant, FunctionProto, OpCode},
+    error::LoxError,
+    parser::{Expr, FunctionDecl, Stmt},
+    token::{Span, Token, TokenType},
+    value::Value,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+struct Local {
+    name: String,
+    /// `None` until the variable's initializer has been compiled.
+    depth: Option<usize>,
+    is_captured: bool,
+}
+
+/// A loop being compiled, for the jumps out of it.
+struct Loop {
+    /// The scope depth outside the loop body; locals deeper than this are
+    /// discarded before jumping.
+    scope_depth: usize,
+    /// `break` jumps, patched to land after the loop.
+    breaks: Vec<usize>,
+    /// `continue` jumps, patched to land on the increment, or on the
+    /// jump back to the condition.
+    continues: Vec<usize>,
+}
+
+struct UpvalueRef {
+    index: u8,
+    is_local: bool,
+}
+
+/// Book-keeping for the function currently being compiled.
+struct FunctionState {
+    name: String,
+    arity: usize,
+    kind: FunctionKind,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+    loops: Vec<Loop>,
+}
+
+impl FunctionState {
+    fn new(name: String, kind: FunctionKind) -> Self {
+        // Slot zero holds the callee, or the receiver inside methods.
+        let slot_zero = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            _ => "",
+        };
+
+        Self {
+            name,
+            arity: 0,
+            kind,
+            chunk: Chunk::new(),
+            locals: vec![Local { name: slot_zero.to_string(), depth: Some(0), is_captured: false }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+            loops: Vec::new(),
+        }
+    }
+}
+
+/// Compiles resolved statements into bytecode for the virtual machine.
+pub struct Compiler {
+    functions: Vec<FunctionState>,
+    line: usize,
+    /// The innermost statement or expression being compiled, for errors.
+    span: Span,
+    errors: Vec<LoxError>,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self {
+            functions: Vec::new(),
+            line: 1,
+            span: Span::default(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Compiles a whole program into the implicit top-level function.
+    pub fn compile(&mut self, statements: &[Stmt]) -> Result<Rc<FunctionProto>, Vec<LoxError>> {
+        self.functions.push(FunctionState::new("script".to_string(), FunctionKind::Script));
+
+        for stmt in statements {
+            self.statement(stmt);
+        }
+
+        self.finish_script()
+    }
+
+    /// Compiles a lone expression into a top-level function that returns its
+    /// value, for the REPL to echo.
+    pub fn compile_expression(&mut self, expr: &Expr) -> Result<Rc<FunctionProto>, Vec<LoxError>> {
+        self.functions.push(FunctionState::new("script".to_string(), FunctionKind::Script));
+        self.expression(expr);
+        self.emit_op(OpCode::Return);
+        self.finish_script()
+    }
+
+    fn finish_script(&mut self) -> Result<Rc<FunctionProto>, Vec<LoxError>> {
+        let (function, _) = self.end_function();
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors));
+        }
+        Ok(Rc::new(function))
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        self.span = stmt.span();
+        match stmt {
+            Stmt::Expression(expr) => {
+                self.expression(expr);
+                self.emit_op(OpCode::Pop);
+            },
+            Stmt::Print(expr, span) => {
+                self.expression(expr);
+                self.span = *span;
+                self.emit_op(OpCode::Print);
+            },
+            Stmt::Let(name, initializer) => {
+                self.line = name.line;
+                self.declare_variable(name);
+                match initializer {
+                    Some(initializer) => self.expression(initializer),
+                    None => self.emit_op(OpCode::Nil),
+                }
+                self.define_variable(name);
+            },
+            Stmt::Block(statements, _) => {
+                self.begin_scope();
+                for stmt in statements {
+                    self.statement(stmt);
+                }
+                self.end_scope();
+            },
+            Stmt::If(cond, then, else_, _) => {
+                self.expression(cond);
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(then);
+
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump);
+                self.emit_op(OpCode::Pop);
+                if let Some(else_) = else_.as_ref() {
+                    self.statement(else_);
+                }
+                self.patch_jump(else_jump);
+            },
+            Stmt::While(cond, body, increment, _) => {
+                let loop_start = self.current_chunk().code.len();
+                self.expression(cond);
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+
+                let scope_depth = self.current().scope_depth;
+                self.current().loops.push(Loop { scope_depth, breaks: Vec::new(), continues: Vec::new() });
+                self.statement(body);
+                let Loop { breaks, continues, .. } = self.current().loops.pop().unwrap();
+
+                for jump in continues {
+                    self.patch_jump(jump);
+                }
+                if let Some(increment) = increment {
+                    self.expression(increment);
+                    self.emit_op(OpCode::Pop);
+                }
+                self.emit_loop(loop_start);
+
+                self.patch_jump(exit_jump);
+                self.emit_op(OpCode::Pop);
+                for jump in breaks {
+                    self.patch_jump(jump);
+                }
+            },
+            Stmt::Function(declaration) => {
+                self.line = declaration.name.line;
+                self.declare_variable(&declaration.name);
+                self.mark_initialized();
+                self.function(declaration, FunctionKind::Function);
+                self.define_variable(&declaration.name);
+            },
+            Stmt::Return(keyword, value) => {
+                self.line = keyword.line;
+                match value {
+                    Some(value) => {
+                        self.expression(value);
+                        self.emit_op(OpCode::Return);
+                    },
+                    None => self.emit_return(),
+                }
+            },
+            Stmt::Class(name, superclass, methods) => self.class_declaration(name, superclass, methods),
+            Stmt::Break(keyword) | Stmt::Continue(keyword) => {
+                self.line = keyword.line;
+                let Some(scope_depth) = self.current().loops.last().map(|innermost| innermost.scope_depth) else {
+                    return self.error("Can't jump outside of a loop.");
+                };
+                self.discard_locals(scope_depth);
+
+                let jump = self.emit_jump(OpCode::Jump);
+                let innermost = self.current().loops.last_mut().unwrap();
+                if matches!(stmt, Stmt::Break(_)) {
+                    innermost.breaks.push(jump);
+                } else {
+                    innermost.continues.push(jump);
+                }
+            },
+        }
+    }
+
+    fn class_declaration(&mut self, name: &Token, superclass: &Option<Expr>, methods: &[FunctionDecl]) {
+        self.line = name.line;
+        let name_constant = self.identifier_constant(&name.lexeme);
+        let is_local = self.current().scope_depth > 0;
+
+        self.declare_variable(name);
+        if is_local {
+            self.emit_op(OpCode::Nil);
+            self.mark_initialized();
+        }
+
+        if let Some(superclass) = superclass {
+            self.begin_scope();
+            self.expression(superclass);
+            self.add_local("super");
+            self.mark_initialized();
+        }
+
+        if methods.len() > u8::MAX as usize {
+            self.error("Can't have more than 255 methods in one class.");
+        }
+        for method in methods {
+            let kind = if method.name.lexeme == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            self.function(method, kind);
+        }
+
+        self.line = name.line;
+        if let Some(superclass) = superclass {
+            self.span = superclass.span();
+        }
+        self.emit_op(OpCode::Class);
+        self.emit_u16(name_constant);
+        self.emit_byte(methods.len() as u8);
+        self.emit_byte(superclass.is_some() as u8);
+
+        if is_local {
+            let slot = self.resolve_local(self.functions.len() - 1, &name.lexeme);
+            self.emit_op(OpCode::SetLocal);
+            self.emit_byte(slot.unwrap_or(0));
+            self.emit_op(OpCode::Pop);
+        } else {
+            self.emit_op(OpCode::DefineGlobal);
+            self.emit_u16(name_constant);
+        }
+
+        if superclass.is_some() {
+            self.end_scope();
+        }
+    }
+
+    fn function(&mut self, declaration: &FunctionDecl, kind: FunctionKind) {
+        self.functions.push(FunctionState::new(declaration.name.lexeme.clone(), kind));
+        self.begin_scope();
+
+        for param in &declaration.params {
+            self.current().arity += 1;
+            self.add_local(&param.lexeme);
+            self.mark_initialized();
+        }
+
+        for stmt in &declaration.body {
+            self.statement(stmt);
+        }
+
+        let (function, upvalues) = self.end_function();
+
+        let constant = self.make_constant(Constant::Function(Rc::new(function)));
+        self.emit_op(OpCode::Closure);
+        self.emit_u16(constant);
+        for upvalue in upvalues {
+            self.emit_byte(upvalue.is_local as u8);
+            self.emit_byte(upvalue.index);
+        }
+    }
+
+    fn end_function(&mut self) -> (FunctionProto, Vec<UpvalueRef>) {
+        self.emit_return();
+        let state = self.functions.pop().unwrap();
+
+        let function = FunctionProto {
+            name: state.name,
+            arity: state.arity,
+            upvalue_count: state.upvalues.len(),
+            chunk: state.chunk,
+        };
+        (function, state.upvalues)
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        self.span = expr.span();
+        match expr {
+            Expr::Literal(value, _) => self.literal(value),
+            Expr::Grouping(expr, _) => self.expression(expr),
+            Expr::Unary(op, right) => {
+                self.expression(right);
+                self.line = op.line;
+                self.span = expr.span();
+                match op.token_type {
+                    TokenType::Minus => self.emit_op(OpCode::Negate),
+                    _ => self.emit_op(OpCode::Not),
+                }
+            },
+            Expr::Binary(left, op, right) => {
+                self.expression(left);
+                self.expression(right);
+                self.line = op.line;
+                self.span = expr.span();
+                match op.token_type {
+                    TokenType::Plus => self.emit_op(OpCode::Add),
+                    TokenType::Minus => self.emit_op(OpCode::Subtract),
+                    TokenType::Star => self.emit_op(OpCode::Multiply),
+                    TokenType::Slash => self.emit_op(OpCode::Divide),
+                    TokenType::EqualEqual => self.emit_op(OpCode::Equal),
+                    TokenType::BangEqual => self.emit_ops(OpCode::Equal, OpCode::Not),
+                    TokenType::Greater => self.emit_op(OpCode::Greater),
+                    TokenType::GreaterEqual => self.emit_ops(OpCode::Less, OpCode::Not),
+                    TokenType::Less => self.emit_op(OpCode::Less),
+                    TokenType::LessEqual => self.emit_ops(OpCode::Greater, OpCode::Not),
+                    _ => self.error(&format!("Unknown binary operator '{}'.", op.lexeme)),
+                }
+            },
+            Expr::Ternary(cond, left, right) => {
+                self.expression(cond);
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.expression(left);
+                let end_jump = self.emit_jump(OpCode::Jump);
+
+                self.patch_jump(else_jump);
+                self.emit_op(OpCode::Pop);
+                self.expression(right);
+                self.patch_jump(end_jump);
+            },
+            Expr::Logical(left, op, right) => {
+                self.expression(left);
+                if op.token_type == TokenType::Or {
+                    let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    let end_jump = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(else_jump);
+                    self.emit_op(OpCode::Pop);
+                    self.expression(right);
+                    self.patch_jump(end_jump);
+                } else {
+                    let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    self.emit_op(OpCode::Pop);
+                    self.expression(right);
+                    self.patch_jump(end_jump);
+                }
+            },
+            Expr::Variable(name, _) => {
+                self.line = name.line;
+                self.span = name.span;
+                self.named_variable(&name.lexeme, false);
+            },
+            Expr::Assign(name, value, _) => {
+                self.expression(value);
+                self.line = name.line;
+                self.span = name.span;
+                self.named_variable(&name.lexeme, true);
+            },
+            Expr::Call(callee, paren, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                self.line = paren.line;
+                if arguments.len() > u8::MAX as usize {
+                    self.error("Can't have more than 255 arguments.");
+                }
+                // Calling something that can't be called points at the
+                // callee; a call that goes wrong points at the parenthesis.
+                self.span = callee.span();
+                self.emit_op(OpCode::Call);
+                self.span = paren.span;
+                self.emit_byte(arguments.len() as u8);
+            },
+            Expr::Get(object, name) => {
+                self.expression(object);
+                self.line = name.line;
+                let constant = self.identifier_constant(&name.lexeme);
+                self.span = object.span();
+                self.emit_op(OpCode::GetProperty);
+                self.span = name.span;
+                self.emit_u16(constant);
+            },
+            Expr::Set(object, name, value) => {
+                self.expression(object);
+                self.expression(value);
+                self.line = name.line;
+                let constant = self.identifier_constant(&name.lexeme);
+                self.span = object.span();
+                self.emit_op(OpCode::SetProperty);
+                self.emit_u16(constant);
+            },
+            Expr::This(keyword, _) => {
+                self.line = keyword.line;
+                self.named_variable("this", false);
+            },
+            Expr::Super(keyword, method, _) => {
+                self.line = keyword.line;
+                let constant = self.identifier_constant(&method.lexeme);
+                self.named_variable("this", false);
+                self.named_variable("super", false);
+                self.span = expr.span();
+                self.emit_op(OpCode::GetSuper);
+                self.span = method.span;
+                self.emit_u16(constant);
+            },
+            Expr::List(items, _) => {
+                for item in items {
+                    self.expression(item);
+                }
+                if items.len() > u16::MAX as usize {
+                    self.error("Too many items in one list literal.");
+                }
+                self.emit_op(OpCode::BuildList);
+                self.emit_u16(items.len() as u16);
+            },
+            Expr::Map(entries, _) => {
+                for (key, _, value) in entries {
+                    self.expression(key);
+                    self.expression(value);
+                }
+                if entries.len() > u16::MAX as usize {
+                    self.error("Too many entries in one map literal.");
+                }
+                self.span = expr.span();
+                self.emit_op(OpCode::BuildMap);
+                self.emit_u16(entries.len() as u16);
+            },
+            Expr::Index(object, bracket, index) => {
+                self.expression(object);
+                self.expression(index);
+                self.line = bracket.line;
+                self.span = expr.span();
+                self.emit_op(OpCode::GetIndex);
+            },
+            Expr::SetIndex(object, bracket, index, value) => {
+                self.expression(object);
+                self.expression(index);
+                self.expression(value);
+                self.line = bracket.line;
+                self.span = expr.span();
+                self.emit_op(OpCode::SetIndex);
+            },
+        }
+    }
+
+    fn literal(&mut self, value: &Value) {
+        match value {
+            Value::Nil => self.emit_op(OpCode::Nil),
+            Value::Bool(true) => self.emit_op(OpCode::True),
+            Value::Bool(false) => self.emit_op(OpCode::False),
+            Value::Number(n) => self.emit_constant(Constant::Number(*n)),
+            Value::String(s) => self.emit_constant(Constant::String(s.clone())),
+            _ => self.error("Only numbers, strings, booleans and nil can be literals."),
+        }
+    }
+
+    fn named_variable(&mut self, name: &str, assign: bool) {
+        let current = self.functions.len() - 1;
+
+        let (get_op, set_op, operand) = if let Some(slot) = self.resolve_local(current, name) {
+            (OpCode::GetLocal, OpCode::SetLocal, slot as u16)
+        } else if let Some(index) = self.resolve_upvalue(current, name) {
+            (OpCode::GetUpvalue, OpCode::SetUpvalue, index as u16)
+        } else {
+            let constant = self.identifier_constant(name);
+            let op = if assign { OpCode::SetGlobal } else { OpCode::GetGlobal };
+            self.emit_op(op);
+            self.emit_u16(constant);
+            return;
+        };
+
+        self.emit_op(if assign { set_op } else { get_op });
+        self.emit_byte(operand as u8);
+    }
+
+    fn resolve_local(&self, function: usize, name: &str) -> Option<u8> {
+        self.functions[function]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u8)
+    }
+
+    /// Finds `name` in an enclosing function and threads it down as an
+    /// upvalue through every function in between.
+    fn resolve_upvalue(&mut self, function: usize, name: &str) -> Option<u8> {
+        if function == 0 {
+            return None;
+        }
+
+        if let Some(local) = self.resolve_local(function - 1, name) {
+            self.functions[function - 1].locals[local as usize].is_captured = true;
+            return Some(self.add_upvalue(function, local, true));
+        }
+
+        let upvalue = self.resolve_upvalue(function - 1, name)?;
+        Some(self.add_upvalue(function, upvalue, false))
+    }
+
+    fn add_upvalue(&mut self, function: usize, index: u8, is_local: bool) -> u8 {
+        let upvalues = &self.functions[function].upvalues;
+        if let Some(existing) = upvalues.iter().position(|u| u.index == index && u.is_local == is_local) {
+            return existing as u8;
+        }
+
+        if upvalues.len() > u8::MAX as usize {
+            self.error("Too many closure variables in function.");
+            return 0;
+        }
+
+        self.functions[function].upvalues.push(UpvalueRef { index, is_local });
+        (self.functions[function].upvalues.len() - 1) as u8
+    }
+
+    fn declare_variable(&mut self, name: &Token) {
+        if self.current().scope_depth == 0 {
+            return;
+        }
+        self.add_local(&name.lexeme);
+    }
+
+    fn define_variable(&mut self, name: &Token) {
+        if self.current().scope_depth > 0 {
+            self.mark_initialized();
+            return;
+        }
+
+        let constant = self.identifier_constant(&name.lexeme);
+        self.emit_op(OpCode::DefineGlobal);
+        self.emit_u16(constant);
+    }
+
+    fn add_local(&mut self, name: &str) {
+        if self.current().locals.len() > u8::MAX as usize {
+            self.error("Too many local variables in function.");
+            return;
+        }
+
+        self.current().locals.push(Local { name: name.to_string(), depth: None, is_captured: false });
+    }
+
+    fn mark_initialized(&mut self) {
+        let depth = self.current().scope_depth;
+        if depth == 0 {
+            return;
+        }
+        if let Some(local) = self.current().locals.last_mut() {
+            local.depth = Some(depth);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.current().scope_depth -= 1;
+        let depth = self.current().scope_depth;
+
+        while let Some(local) = self.current().locals.last() {
+            if local.depth.is_some_and(|d| d <= depth) {
+                break;
+            }
+
+            if local.is_captured {
+                self.emit_op(OpCode::CloseUpvalue);
+            } else {
+                self.emit_op(OpCode::Pop);
+            }
+            self.current().locals.pop();
+        }
+    }
+
+    /// Pops the locals declared deeper than `depth` off the stack at run
+    /// time, for a jump out of their scopes. The compiler keeps them, since
+    /// the code after the jump is still inside those scopes. A closure
+    /// further down may capture any of them yet, so each one is closed.
+    fn discard_locals(&mut self, depth: usize) {
+        let count = self.current().locals.iter().rev()
+            .take_while(|local| local.depth.is_none_or(|d| d > depth))
+            .count();
+        for _ in 0..count {
+            self.emit_op(OpCode::CloseUpvalue);
+        }
+    }
+
+    fn identifier_constant(&mut self, name: &str) -> u16 {
+        self.make_constant(Constant::String(name.to_string()))
+    }
+
+    fn make_constant(&mut self, constant: Constant) -> u16 {
+        let index = self.current_chunk().add_constant(constant);
+        if index > u16::MAX as usize {
+            self.error("Too many constants in one chunk.");
+            return 0;
+        }
+        index as u16
+    }
+
+    fn emit_constant(&mut self, constant: Constant) {
+        let index = self.make_constant(constant);
+        self.emit_op(OpCode::Constant);
+        self.emit_u16(index);
+    }
+
+    fn emit_return(&mut self) {
+        if self.current().kind == FunctionKind::Initializer {
+            self.emit_op(OpCode::GetLocal);
+            self.emit_byte(0);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+        self.emit_op(OpCode::Return);
+    }
+
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_u16(u16::MAX);
+        self.current_chunk().code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize) {
+        let jump = self.current_chunk().code.len() - offset - 2;
+        if jump > u16::MAX as usize {
+            self.error("Too much code to jump over.");
+            return;
+        }
+
+        let bytes = (jump as u16).to_be_bytes();
+        self.current_chunk().code[offset] = bytes[0];
+        self.current_chunk().code[offset + 1] = bytes[1];
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit_op(OpCode::Loop);
+        let offset = self.current_chunk().code.len() - loop_start + 2;
+        if offset > u16::MAX as usize {
+            self.error("Loop body too large.");
+            return;
+        }
+        self.emit_u16(offset as u16);
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_ops(&mut self, first: OpCode, second: OpCode) {
+        self.emit_op(first);
+        self.emit_op(second);
+    }
+
+    fn emit_u16(&mut self, value: u16) {
+        let bytes = value.to_be_bytes();
+        self.emit_byte(bytes[0]);
+        self.emit_byte(bytes[1]);
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let (line, span) = (self.line, self.span);
+        self.current_chunk().write(byte, line, span);
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn current_chunk(&mut self) -> &mut Chunk {
+        &mut self.current().chunk
+    }
+
+    fn error(&mut self, msg: &str) {
+        self.errors.push(LoxError::Compile { line: self.line, span: self.span, message: msg.to_string() });
+    }
+}
@@ -0,0 +1,131 @@
+use std::fmt::Write;
+
+use crate::{error::LoxError, token::Span};
+
+/// One underlined span: `^` for the error itself, `-` for secondary labels.
+struct Mark<'a> {
+    line: usize,
+    column: usize,
+    width: usize,
+    underline: char,
+    message: &'a str,
+}
+
+impl<'a> Mark<'a> {
+    /// `None` without a source, or if `span` isn't a range of it, as when
+    /// the span came from a hand-written syntax tree.
+    fn new(source: &str, span: Span, underline: char, message: &'a str) -> Option<Self> {
+        let Span { start, end } = span;
+        let fits = start <= end && end <= source.len() && source.is_char_boundary(start) && source.is_char_boundary(end);
+        if source.is_empty() || !fits {
+            return None;
+        }
+        let (line, line_start, line_end) = locate(source, start);
+        let end = end.min(line_end);
+
+        Some(Mark {
+            line,
+            column: source[line_start..start].chars().count() + 1,
+            width: source[start..end].chars().count().max(1),
+            underline,
+            message,
+        })
+    }
+}
+
+/// Renders `error` the way rustc does: a header naming the stage, the file
+/// and position, then each source line involved with its spans underlined
+/// and the messages beside the underlines.
+///
+/// ```text
+/// error[resolve]
+///  --> script.lox:3:9
+///   |
+/// 2 |     var a = 1;
+///   |         - first declared here
+/// 3 |     var a = 2;
+///   |         ^ Already a variable with this name in this scope.
+/// ```
+///
+/// Without `source`, as for compiled scripts and JSON syntax trees, or when
+/// a span doesn't fit in it, only the position and message are shown.
+pub fn render(error: &LoxError, file: &str, source: &str) -> String {
+    render_at(error, file, source, 0)
+}
+
+/// Like `render`, for `source` that starts `offset` bytes into the text the
+/// error's spans point into, such as one input of a REPL session.
+pub fn render_at(error: &LoxError, file: &str, source: &str, offset: usize) -> String {
+    let local = |span: Span| Some(Span::new(span.start.checked_sub(offset)?, span.end.checked_sub(offset)?));
+    let primary = error.span().and_then(local).and_then(|span| Mark::new(source, span, '^', error.message()));
+    let (line, column) = match &primary {
+        Some(mark) => (mark.line, Some(mark.column)),
+        // Tokens built without a position have column 0.
+        None => (error.line(), error.column().filter(|&column| column > 0)),
+    };
+
+    let mut marks: Vec<Mark> = error.labels()
+        .iter()
+        .filter_map(|label| Mark::new(source, local(label.span)?, '-', &label.message))
+        .collect();
+    marks.extend(primary);
+    marks.sort_by_key(|mark| (mark.line, mark.column));
+
+    let last_line = marks.iter().map(|mark| mark.line).max().unwrap_or(line).max(line);
+    let gutter = " ".repeat(last_line.to_string().len());
+
+    let mut out = String::new();
+    writeln!(out, "error[{}]", error.stage()).unwrap();
+    match column {
+        Some(column) => writeln!(out, "{gutter}--> {file}:{line}:{column}").unwrap(),
+        None => writeln!(out, "{gutter}--> {file}:{line}").unwrap(),
+    }
+    writeln!(out, "{gutter} |").unwrap();
+
+    if marks.is_empty() {
+        // Without a span there is nothing to underline, so show the line and
+        // put the message under it.
+        // Line 0 means the error has no position at all.
+        let text = if line > 0 && !source.is_empty() { line_text(source, line) } else { None };
+        if let Some(text) = text {
+            writeln!(out, "{line:>width$} | {text}", width = gutter.len()).unwrap();
+        }
+        writeln!(out, "{gutter} = {}", error.message()).unwrap();
+        return out;
+    }
+
+    let mut shown = 0;
+    for mark in &marks {
+        if mark.line != shown {
+            let text = line_text(source, mark.line).unwrap_or_default();
+            writeln!(out, "{:>width$} | {text}", mark.line, width = gutter.len()).unwrap();
+            shown = mark.line;
+        }
+
+        writeln!(
+            out,
+            "{gutter} | {}{} {}",
+            " ".repeat(mark.column - 1),
+            mark.underline.to_string().repeat(mark.width),
+            mark.message
+        ).unwrap();
+    }
+    out
+}
+
+/// The 1-based line containing byte `offset`, and that line's byte range.
+fn locate(source: &str, offset: usize) -> (usize, usize, usize) {
+    let line = source[..offset].matches('\n').count() + 1;
+    let start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = source[offset..].find('\n').map_or(source.len(), |i| offset + i);
+    (line, start, end)
+}
+
+/// The text of the 1-based `line`, with tabs shown as single spaces so the
+/// underline lines up.
+fn line_text(source: &str, line: usize) -> Option<String> {
+    source
+        .split('\n')
+        .nth(line - 1)
+        .map(|text| text.trim_end_matches('\r').replace('\t', " "))
+}
@@ -0,0 +1,450 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, BufRead, IsTerminal, Read, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+/// How many lines of history are kept between sessions.
+const HISTORY_LIMIT: usize = 1000;
+
+/// A key press, decoded from the bytes a terminal sends in raw mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Char(char),
+    /// Ctrl plus a letter, e.g. `Ctrl('r')`.
+    Ctrl(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    Other,
+}
+
+/// The line being edited and the cursor position within it, in characters.
+#[derive(Default)]
+struct Line {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl Line {
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    fn insert(&mut self, ch: char) {
+        self.chars.insert(self.cursor, ch);
+        self.cursor += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.chars.len() {
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    /// Where the identifier ending at the cursor starts.
+    fn word_start(&self) -> usize {
+        self.chars[..self.cursor]
+            .iter()
+            .rposition(|ch| !(ch.is_alphanumeric() || *ch == '_'))
+            .map_or(0, |i| i + 1)
+    }
+}
+
+/// A small line editor for the REPL: cursor movement, history with
+/// up/down and Ctrl-R search, and tab completion.
+pub struct Editor {
+    history: Vec<String>,
+    /// Where history is saved, if anywhere.
+    path: Option<PathBuf>,
+    /// How many lines the history file holds, so it can be cut back to
+    /// `HISTORY_LIMIT` once appending would take it over.
+    saved: usize,
+}
+
+impl Editor {
+    /// An editor whose history is loaded from and appended to `path`.
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let mut history: Vec<String> = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(String::from).collect())
+            .unwrap_or_default();
+        let saved = history.len();
+        if history.len() > HISTORY_LIMIT {
+            history.drain(..history.len() - HISTORY_LIMIT);
+        }
+
+        Editor { history, path, saved }
+    }
+
+    /// Reads one line, without its newline, or `None` at end of input.
+    /// Ctrl-C abandons the line with an `Interrupted` error. When stdin
+    /// isn't a terminal, lines are read as they are, with no editing.
+    pub fn read_line(&mut self, prompt: &str, names: &[String]) -> io::Result<Option<String>> {
+        let stdin = io::stdin();
+        let raw = if stdin.is_terminal() { RawMode::enable().ok() } else { None };
+        if raw.is_none() {
+            return read_plain(prompt);
+        }
+
+        self.edit(prompt, names, &mut stdin.lock(), &mut io::stdout())
+    }
+
+    /// Runs the editor over the keys read from `input`, drawing to `output`.
+    /// `names` are offered by tab completion.
+    pub fn edit(
+        &mut self, prompt: &str, names: &[String],
+        input: &mut impl Read, output: &mut impl Write
+    ) -> io::Result<Option<String>> {
+        let mut line = Line::default();
+        // The history entry being shown, and the line typed before browsing.
+        let mut browsing: Option<usize> = None;
+        let mut draft = String::new();
+        // A key that ended a search and still has to be handled.
+        let mut pending: Option<Key> = None;
+
+        loop {
+            refresh(output, prompt, &line)?;
+            let key = match pending.take() {
+                Some(key) => key,
+                None => match read_key(input)? {
+                    Some(key) => key,
+                    None if line.chars.is_empty() => return Ok(None),
+                    None => Key::Enter,
+                },
+            };
+
+            match key {
+                Key::Enter => {
+                    write!(output, "\r\n")?;
+                    let text = line.text();
+                    self.add_history(&text);
+                    return Ok(Some(text));
+                },
+                Key::Ctrl('c') => {
+                    write!(output, "^C\r\n")?;
+                    return Err(io::ErrorKind::Interrupted.into());
+                },
+                Key::Ctrl('d') if line.chars.is_empty() => {
+                    write!(output, "\r\n")?;
+                    return Ok(None);
+                },
+                Key::Ctrl('d') | Key::Delete => line.delete(),
+                Key::Backspace => line.backspace(),
+                Key::Left | Key::Ctrl('b') => line.cursor = line.cursor.saturating_sub(1),
+                Key::Right | Key::Ctrl('f') => line.cursor = (line.cursor + 1).min(line.chars.len()),
+                Key::Home | Key::Ctrl('a') => line.cursor = 0,
+                Key::End | Key::Ctrl('e') => line.cursor = line.chars.len(),
+                Key::Ctrl('k') => line.chars.truncate(line.cursor),
+                Key::Ctrl('u') => {
+                    line.chars.drain(..line.cursor);
+                    line.cursor = 0;
+                },
+                Key::Up | Key::Ctrl('p') => {
+                    let older = match browsing {
+                        None => self.history.len().checked_sub(1),
+                        Some(i) => i.checked_sub(1),
+                    };
+                    if let Some(i) = older {
+                        if browsing.is_none() {
+                            draft = line.text();
+                        }
+                        browsing = Some(i);
+                        line.set(&self.history[i]);
+                    }
+                },
+                Key::Down | Key::Ctrl('n') => {
+                    if let Some(i) = browsing {
+                        if i + 1 < self.history.len() {
+                            browsing = Some(i + 1);
+                            line.set(&self.history[i + 1]);
+                        } else {
+                            browsing = None;
+                            line.set(&draft);
+                        }
+                    }
+                },
+                Key::Tab => complete(&mut line, names, output)?,
+                Key::Ctrl('r') => pending = self.reverse_search(input, output, &mut line)?,
+                Key::Char(ch) => line.insert(ch),
+                _ => {},
+            }
+        }
+    }
+
+    /// Searches history backwards for lines containing what is typed. Any
+    /// key other than the search keys puts the match on the line and is
+    /// returned to be handled as usual; Ctrl-G or Ctrl-C give up instead.
+    fn reverse_search(
+        &self, input: &mut impl Read, output: &mut impl Write, line: &mut Line
+    ) -> io::Result<Option<Key>> {
+        let mut query = String::new();
+        let mut found: Option<usize> = None;
+
+        loop {
+            let shown = found.map_or("", |i| self.history[i].as_str());
+            write!(output, "\r(reverse-i-search)`{}': {}\x1b[K", query, shown)?;
+            output.flush()?;
+
+            let Some(key) = read_key(input)? else { return Ok(None); };
+            match key {
+                Key::Char(ch) => {
+                    query.push(ch);
+                    found = self.find(&query, self.history.len());
+                },
+                Key::Backspace => {
+                    query.pop();
+                    found = self.find(&query, self.history.len());
+                },
+                Key::Ctrl('r') => {
+                    let before = found.unwrap_or(self.history.len());
+                    found = self.find(&query, before).or(found);
+                },
+                Key::Ctrl('g') | Key::Ctrl('c') => return Ok(None),
+                key => {
+                    if let Some(i) = found {
+                        line.set(&self.history[i]);
+                    }
+                    return Ok(Some(key));
+                },
+            }
+        }
+    }
+
+    /// The latest history entry before `before` that contains `query`.
+    fn find(&self, query: &str, before: usize) -> Option<usize> {
+        if query.is_empty() {
+            return None;
+        }
+        self.history[..before].iter().rposition(|entry| entry.contains(query))
+    }
+
+    /// Remembers `line`, and appends it to the history file. Blank lines
+    /// and repeats of the previous line are skipped. Past `HISTORY_LIMIT`
+    /// lines, the oldest are dropped, and the file is rewritten to match.
+    fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if self.history.len() > HISTORY_LIMIT {
+            self.history.drain(..self.history.len() - HISTORY_LIMIT);
+        }
+
+        let Some(path) = &self.path else { return; };
+        // History is a convenience, so failing to save it isn't an error.
+        if self.saved < HISTORY_LIMIT {
+            let _ = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", line));
+            self.saved += 1;
+        } else {
+            let text: String = self.history.iter().map(|entry| format!("{}\n", entry)).collect();
+            let _ = fs::write(path, text);
+            self.saved = self.history.len();
+        }
+    }
+}
+
+/// `~/.rlox_history`, if the home directory is known.
+pub fn history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".rlox_history"))
+}
+
+/// Redraws the prompt and line, then puts the cursor back in place.
+fn refresh(output: &mut impl Write, prompt: &str, line: &Line) -> io::Result<()> {
+    write!(output, "\r{}{}\x1b[K", prompt, line.text())?;
+    let back = line.chars.len() - line.cursor;
+    if back > 0 {
+        write!(output, "\x1b[{}D", back)?;
+    }
+    output.flush()
+}
+
+/// Completes the word before the cursor from `names`. With a single match
+/// the word is finished; with several it is extended as far as they agree,
+/// and if that adds nothing they are listed.
+fn complete(line: &mut Line, names: &[String], output: &mut impl Write) -> io::Result<()> {
+    let start = line.word_start();
+    let word: String = line.chars[start..line.cursor].iter().collect();
+    if word.is_empty() {
+        return Ok(());
+    }
+
+    let mut matches: Vec<&str> = names
+        .iter()
+        .map(String::as_str)
+        .filter(|name| name.starts_with(&word))
+        .collect();
+    matches.sort();
+    matches.dedup();
+    let Some(first) = matches.first() else { return Ok(()); };
+
+    let prefix = matches
+        .iter()
+        .map(|name| shared_prefix(first, name))
+        .min()
+        .unwrap_or(first.len());
+
+    if prefix > word.len() {
+        for ch in first[word.len()..prefix].chars() {
+            line.insert(ch);
+        }
+    } else if matches.len() > 1 {
+        write!(output, "\r\n{}\r\n", matches.join("  "))?;
+    }
+    Ok(())
+}
+
+/// The length in bytes of the prefix `a` and `b` have in common.
+fn shared_prefix(a: &str, b: &str) -> usize {
+    a.chars()
+        .zip(b.chars())
+        .take_while(|(x, y)| x == y)
+        .map(|(x, _)| x.len_utf8())
+        .sum()
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match input.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+/// Reads one key, or `None` at end of input.
+fn read_key(input: &mut impl Read) -> io::Result<Option<Key>> {
+    let Some(byte) = read_byte(input)? else { return Ok(None); };
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x08 | 0x7f => Key::Backspace,
+        0x1b => read_escape(input)?,
+        0x01..=0x1a => Key::Ctrl((b'a' + byte - 1) as char),
+        0x00..=0x1f => Key::Other,
+        _ => read_char(byte, input)?,
+    };
+    Ok(Some(key))
+}
+
+/// Decodes the rest of an escape sequence such as `ESC [ A`.
+fn read_escape(input: &mut impl Read) -> io::Result<Key> {
+    if !matches!(read_byte(input)?, Some(b'[') | Some(b'O')) {
+        return Ok(Key::Other);
+    }
+
+    let key = match read_byte(input)? {
+        Some(b'A') => Key::Up,
+        Some(b'B') => Key::Down,
+        Some(b'C') => Key::Right,
+        Some(b'D') => Key::Left,
+        Some(b'H') => Key::Home,
+        Some(b'F') => Key::End,
+        Some(digit @ b'0'..=b'9') => {
+            // `ESC [ n ~`, with any further parameters ignored.
+            let mut code = vec![digit];
+            while let Some(byte) = read_byte(input)? {
+                if byte == b'~' {
+                    break;
+                }
+                code.push(byte);
+            }
+            match code.as_slice() {
+                b"1" | b"7" => Key::Home,
+                b"4" | b"8" => Key::End,
+                b"3" => Key::Delete,
+                _ => Key::Other,
+            }
+        },
+        _ => Key::Other,
+    };
+    Ok(key)
+}
+
+/// Decodes a character whose UTF-8 encoding starts with `first`.
+fn read_char(first: u8, input: &mut impl Read) -> io::Result<Key> {
+    let len = match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    };
+    let mut bytes = vec![first];
+    for _ in 1..len {
+        match read_byte(input)? {
+            Some(byte) => bytes.push(byte),
+            None => break,
+        }
+    }
+
+    Ok(std::str::from_utf8(&bytes)
+        .ok()
+        .and_then(|text| text.chars().next())
+        .map_or(Key::Other, Key::Char))
+}
+
+/// Prompts and reads a line from stdin as it is, for piped input.
+fn read_plain(prompt: &str) -> io::Result<Option<String>> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let len = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(len);
+    Ok(Some(line))
+}
+
+/// Puts the terminal in raw mode until dropped, using `stty` so no
+/// platform bindings are needed.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<RawMode> {
+        let saved = stty(&["-g"])?.trim().to_string();
+        stty(&["raw", "-echo"])?;
+        Ok(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
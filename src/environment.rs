@@ -1,11 +1,11 @@
-use std::collections::HashMap;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use crate::{token::Token, Lox};
+use crate::{error::LoxError, token::Token, value::Value};
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct Environment {
-    values: HashMap<String, String>,
-    pub enclosing: Option<Box<Environment>>
+    values: HashMap<String, Value>,
+    pub enclosing: Option<Rc<RefCell<Environment>>>
 }
 
 impl Environment {
@@ -16,51 +16,72 @@ impl Environment {
         }
     }
 
-    pub fn with_enclosing(enclosing: Environment) -> Self {
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
         Self {
             values: HashMap::new(),
-            enclosing: Some(Box::new(enclosing)),
+            enclosing: Some(enclosing),
         }
     }
 
-    pub fn define(&mut self, name: String, value: String) {
+    pub fn define(&mut self, name: String, value: Value) {
         self.values.insert(name, value);
     }
 
-    pub fn get(&mut self, name: Token) -> Option<String> {
-        if self.values.contains_key(&name.lexeme) {
-            let val = self.values.get(&name.lexeme).unwrap().clone();
-            return Some(val);
+    /// Every variable defined directly in this scope.
+    pub fn bindings(&self) -> Vec<(String, Value)> {
+        self.values.iter().map(|(name, value)| (name.clone(), value.clone())).collect()
+    }
+
+    /// Reads `name` from this scope only, without reporting an error.
+    pub fn get_local(&self, name: &str) -> Option<Value> {
+        self.values.get(name).cloned()
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Value, LoxError> {
+        if let Some(val) = self.values.get(&name.lexeme) {
+            return Ok(val.clone());
         }
 
-        if self.enclosing.is_some() {
-            let enclosing = self.enclosing.as_mut().unwrap();
-            let val = enclosing.get(name.clone());
-            if let Some(val) = val {
-                return Some(val);
-            }
+        if let Some(enclosing) = &self.enclosing {
+            return enclosing.borrow().get(name);
         }
 
-        Lox::error(name.line, format!("Undefined Variable '{}'.", name.lexeme));
-        None
+        Err(LoxError::runtime(name, format!("Undefined Variable '{}'.", name.lexeme)))
+    }
+
+    /// Reads a variable the resolver found `distance` scopes out.
+    pub fn get_at(env: &Rc<RefCell<Environment>>, distance: usize, name: &Token) -> Result<Value, LoxError> {
+        let ancestor = Self::ancestor(env, distance);
+        let value = ancestor.borrow().values.get(&name.lexeme).cloned();
+        value.ok_or_else(|| LoxError::runtime(name, format!("Undefined Variable '{}'.", name.lexeme)))
+    }
+
+    pub fn assign_at(env: &Rc<RefCell<Environment>>, distance: usize, name: &Token, value: Value) {
+        Self::ancestor(env, distance)
+            .borrow_mut()
+            .values
+            .insert(name.lexeme.clone(), value);
+    }
+
+    pub fn ancestor(env: &Rc<RefCell<Environment>>, distance: usize) -> Rc<RefCell<Environment>> {
+        let mut env = Rc::clone(env);
+        for _ in 0..distance {
+            let enclosing = env.borrow().enclosing.clone().unwrap();
+            env = enclosing;
+        }
+        env
     }
 
-    pub fn assign(&mut self, name: Token, value: String) -> Option<()> {
-        if self.values.contains_key(&name.lexeme) {
-            self.values.insert(name.lexeme, value);
-            return Some(());
+    pub fn assign(&mut self, name: &Token, value: Value) -> Result<(), LoxError> {
+        if let Some(slot) = self.values.get_mut(&name.lexeme) {
+            *slot = value;
+            return Ok(());
         }
 
-        if let Some(enclosed_env) = self.enclosing.as_mut() {
-            enclosed_env.assign(name.clone(), value);
-            return Some(());
+        if let Some(enclosing) = &self.enclosing {
+            return enclosing.borrow_mut().assign(name, value);
         }
 
-        Lox::report(
-            name.line,
-            format!("at '{}'", name.lexeme),
-            format!("Undefined Variable '{}'.", name.lexeme)
-            );
-        None
+        Err(LoxError::runtime(name, format!("Undefined Variable '{}'.", name.lexeme)))
     }
 }
@@ -0,0 +1,121 @@
+use std::fmt;
+
+use crate::token::{Span, Token, TokenType};
+
+/// A secondary location shown alongside an error, such as where a
+/// variable was first declared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// Everything that can go wrong while running a Lox program, tagged with
+/// the stage that found it. Tokens are boxed to keep `Result`s small on the
+/// interpreter's hot path.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoxError {
+    Scan { line: usize, column: usize, span: Span, message: String },
+    Parse { token: Box<Token>, message: String },
+    Resolve { token: Box<Token>, message: String, labels: Vec<Label> },
+    /// A limit of the bytecode format was exceeded, such as too many
+    /// constants. The span is the innermost node being compiled.
+    Compile { line: usize, span: Span, message: String },
+    /// `span` may be wider than `token`, such as a whole binary expression
+    /// whose operands have the wrong types. The VM has no tokens, so there
+    /// only `span` is set, and not even that for a script loaded from a
+    /// `.loxc` file.
+    Runtime { line: usize, token: Option<Box<Token>>, span: Option<Span>, message: String },
+}
+
+impl LoxError {
+    pub fn runtime(token: &Token, message: impl Into<String>) -> Self {
+        Self::runtime_at(token, token.span, message)
+    }
+
+    /// A runtime error reported at `token` that covers all of `span`.
+    pub fn runtime_at(token: &Token, span: Span, message: impl Into<String>) -> Self {
+        LoxError::Runtime {
+            line: token.line,
+            token: Some(Box::new(token.clone())),
+            span: Some(span),
+            message: message.into()
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        match self {
+            LoxError::Scan { line, .. }
+            | LoxError::Compile { line, .. }
+            | LoxError::Runtime { line, .. } => *line,
+            LoxError::Parse { token, .. } | LoxError::Resolve { token, .. } => token.line,
+        }
+    }
+
+    pub fn column(&self) -> Option<usize> {
+        match self {
+            LoxError::Scan { column, .. } => Some(*column),
+            _ => self.token().map(|token| token.column),
+        }
+    }
+
+    /// The source range the error is about.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            LoxError::Scan { span, .. } | LoxError::Compile { span, .. } => Some(*span),
+            LoxError::Parse { token, .. } | LoxError::Resolve { token, .. } => Some(token.span),
+            LoxError::Runtime { span, .. } => *span,
+        }
+    }
+
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            LoxError::Parse { token, .. } | LoxError::Resolve { token, .. } => Some(token.as_ref()),
+            LoxError::Runtime { token, .. } => token.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn labels(&self) -> &[Label] {
+        match self {
+            LoxError::Resolve { labels, .. } => labels,
+            _ => &[],
+        }
+    }
+
+    /// The stage that found the error, as shown in diagnostics.
+    pub fn stage(&self) -> &'static str {
+        match self {
+            LoxError::Scan { .. } => "scan",
+            LoxError::Parse { .. } => "parse",
+            LoxError::Resolve { .. } => "resolve",
+            LoxError::Compile { .. } => "compile",
+            LoxError::Runtime { .. } => "runtime",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            LoxError::Scan { message, .. }
+            | LoxError::Parse { message, .. }
+            | LoxError::Resolve { message, .. }
+            | LoxError::Compile { message, .. }
+            | LoxError::Runtime { message, .. } => message,
+        }
+    }
+}
+
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.column() {
+            Some(column) => write!(f, "line[{}:{}] Error", self.line(), column)?,
+            None => write!(f, "line[{}] Error", self.line())?,
+        }
+        match self.token() {
+            Some(token) if token.token_type == TokenType::EOF => write!(f, " at end")?,
+            Some(token) => write!(f, " at '{}'", token.lexeme)?,
+            None => {},
+        }
+        write!(f, ": {}", self.message())
+    }
+}
@@ -0,0 +1,262 @@
+use crate::{
+    error::LoxError,
+    parser::Parser,
+    scanner::Scanner,
+    token::{Token, TokenType},
+};
+
+const INDENT: &str = "    ";
+
+/// Formats a Lox program the one canonical way: four-space indentation,
+/// one statement per line, braces on the line that opens them, single
+/// spaces around binary operators, no doubled `;` and at most one blank
+/// line in a row.
+/// Comments stay where they were, either on their own line or after code.
+///
+/// The formatter works on tokens rather than the syntax tree, since the
+/// tree no longer knows a `for` loop from the `while` it became. The
+/// source is parsed first all the same, so only valid programs change.
+pub fn format(source: &str) -> Result<String, Vec<LoxError>> {
+    let mut scanner = Scanner::with_comments(source.to_string());
+    let tokens = scanner.scan_tokens()?.clone();
+
+    let code: Vec<Token> = tokens.iter()
+        .filter(|token| token.token_type != TokenType::Comment)
+        .cloned()
+        .collect();
+    Parser::new(code).parse()?;
+
+    let tokens: Vec<Token> = tokens.into_iter()
+        .filter(|token| token.token_type != TokenType::EOF)
+        .collect();
+
+    let mut formatter = Formatter {
+        out: String::new(),
+        indent: 0,
+        parens: 0,
+        groups: vec![Group { map: false, questions: 0 }],
+        line_break: false,
+    };
+    formatter.tokens(&tokens);
+    Ok(formatter.out)
+}
+
+struct Formatter {
+    out: String,
+    indent: usize,
+    /// How many parentheses are open; `;` inside them doesn't end a line.
+    parens: usize,
+    /// The brackets of every kind that are open, innermost last, after one
+    /// for the program itself.
+    groups: Vec<Group>,
+    /// Whether the next token starts a new line.
+    line_break: bool,
+}
+
+/// A `(`, `[` or `{` that hasn't been closed yet.
+struct Group {
+    /// Whether it is the `{` of a map, which stays on one line, rather than
+    /// a block.
+    map: bool,
+    /// How many `?` inside it still wait for their `:`. A `:` with none
+    /// waiting follows a map key.
+    questions: usize,
+}
+
+impl Formatter {
+    fn tokens(&mut self, tokens: &[Token]) {
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            if token.token_type == TokenType::Semicolon && self.parens == 0 && follows_semicolon(&tokens[..i]) {
+                // `print 1;;` is one statement; the extra `;` says nothing.
+                i += 1;
+                continue;
+            }
+            let prev = i.checked_sub(1).map(|j| &tokens[j]);
+            let before_prev = i.checked_sub(2).map(|j| &tokens[j]);
+            let next = tokens.get(i + 1);
+            let group = self.groups.last().unwrap();
+            let closes_map = token.token_type == TokenType::RightBrace && group.map;
+            let map_colon = token.token_type == TokenType::Colon && group.questions == 0;
+
+            match prev {
+                None => {},
+                // A comment after code on the same line stays there.
+                Some(prev) if token.token_type == TokenType::Comment && token.line == end_line(prev) => {
+                    self.out.push(' ');
+                },
+                Some(prev) => {
+                    if !self.line_break && !closes_map && breaks_before(prev, token, self.parens) {
+                        self.line_break = true;
+                    }
+
+                    if self.line_break {
+                        self.new_line(prev, token, closes_map);
+                    } else if !map_colon && spaced(before_prev, prev, token) {
+                        self.out.push(' ');
+                    }
+                },
+            }
+            self.out.push_str(&token.lexeme);
+
+            match token.token_type {
+                TokenType::LeftParen => {
+                    self.parens += 1;
+                    self.open(false);
+                },
+                TokenType::RightParen => {
+                    self.parens = self.parens.saturating_sub(1);
+                    self.close();
+                },
+                TokenType::LeftBracket => self.open(false),
+                TokenType::RightBracket => self.close(),
+                TokenType::LeftBrace if opens_map(&tokens[..i]) => self.open(true),
+                TokenType::LeftBrace if next.is_some_and(|next| next.token_type == TokenType::RightBrace) => {
+                    // An empty block stays `{}`.
+                    self.out.push('}');
+                    i += 1;
+                    self.line_break = ends_line_after_brace(tokens.get(i + 1));
+                },
+                TokenType::LeftBrace => {
+                    self.open(false);
+                    self.indent += 1;
+                    self.line_break = true;
+                },
+                TokenType::RightBrace => {
+                    self.close();
+                    self.line_break = !closes_map && ends_line_after_brace(next);
+                },
+                TokenType::Qmark => self.groups.last_mut().unwrap().questions += 1,
+                TokenType::Colon => {
+                    let group = self.groups.last_mut().unwrap();
+                    group.questions = group.questions.saturating_sub(1);
+                },
+                TokenType::Semicolon => self.line_break = self.parens == 0,
+                TokenType::Comment => self.line_break = token.lexeme.starts_with("//") || self.line_break,
+                _ => self.line_break = false,
+            }
+            i += 1;
+        }
+
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+    }
+
+    fn open(&mut self, map: bool) {
+        self.groups.push(Group { map, questions: 0 });
+    }
+
+    fn close(&mut self) {
+        if self.groups.len() > 1 {
+            self.groups.pop();
+        }
+    }
+
+    /// Starts the line `token` goes on, keeping one blank line if the
+    /// source had any between it and `prev`.
+    fn new_line(&mut self, prev: &Token, token: &Token, closes_map: bool) {
+        if token.token_type == TokenType::RightBrace && !closes_map {
+            self.indent = self.indent.saturating_sub(1);
+        }
+
+        self.out.push('\n');
+        let blank = token.line > end_line(prev) + 1;
+        if blank && prev.token_type != TokenType::LeftBrace && token.token_type != TokenType::RightBrace {
+            self.out.push('\n');
+        }
+        // Only a comment breaks a map's line; what follows it is indented
+        // once more, like the rest of a block.
+        let maps = self.groups.iter().filter(|group| group.map).count() - closes_map as usize;
+        self.out.push_str(&INDENT.repeat(self.indent + maps));
+        self.line_break = false;
+    }
+}
+
+/// The line a token ends on; strings and block comments can span several.
+fn end_line(token: &Token) -> usize {
+    token.line + token.lexeme.matches('\n').count()
+}
+
+/// Whether `token` goes on a new line even though nothing before it asked
+/// for one: closing braces, comments that had a line to themselves, code
+/// on the line after a block comment, and statements the source put on
+/// their own line without a `;`.
+fn breaks_before(prev: &Token, token: &Token, parens: usize) -> bool {
+    let own_line = token.line > end_line(prev);
+    match token.token_type {
+        TokenType::RightBrace => true,
+        _ if prev.token_type == TokenType::Comment || token.token_type == TokenType::Comment => own_line,
+        _ => own_line && parens == 0 && ends_expression(prev) && starts_statement(token),
+    }
+}
+
+/// Whether the last code token, comments aside, is a `;`.
+fn follows_semicolon(before: &[Token]) -> bool {
+    before.iter()
+        .rfind(|token| token.token_type != TokenType::Comment)
+        .is_some_and(|token| token.token_type == TokenType::Semicolon)
+}
+
+/// Whether a line ends after a `}`. It doesn't when the statement goes on,
+/// as in `} else {`.
+fn ends_line_after_brace(next: Option<&Token>) -> bool {
+    !next.is_some_and(|next| matches!(
+        next.token_type,
+        TokenType::Else | TokenType::Semicolon | TokenType::RightParen | TokenType::Comma | TokenType::Dot
+    ))
+}
+
+/// Whether a `{` after `before` starts a map. A block's `{` follows a `)`,
+/// `else`, a class name or another statement; a map's goes where an
+/// operand would.
+fn opens_map(before: &[Token]) -> bool {
+    use TokenType::*;
+
+    let prev = before.iter().rev().find(|token| token.token_type != Comment);
+    prev.is_some_and(|prev| matches!(
+        prev.token_type,
+        Equal | LeftParen | LeftBracket | Comma | Colon | Qmark | Return | Print | Bang | BangEqual
+            | EqualEqual | Greater | GreaterEqual | Less | LessEqual | Minus | Plus | Slash | Star | And | Or
+    ))
+}
+
+fn ends_expression(token: &Token) -> bool {
+    matches!(
+        token.token_type,
+        TokenType::Identifier | TokenType::Number | TokenType::String | TokenType::RightParen
+            | TokenType::RightBracket | TokenType::This | TokenType::True | TokenType::False | TokenType::Nil
+    )
+}
+
+fn starts_statement(token: &Token) -> bool {
+    matches!(
+        token.token_type,
+        TokenType::Identifier | TokenType::Number | TokenType::String | TokenType::Bang
+            | TokenType::This | TokenType::Super | TokenType::True | TokenType::False | TokenType::Nil
+            | TokenType::Print | TokenType::Var | TokenType::Fun | TokenType::Class | TokenType::If
+            | TokenType::While | TokenType::For | TokenType::Return | TokenType::Break | TokenType::Continue
+    )
+}
+
+/// Whether a space separates `prev` and `token` on the same line.
+fn spaced(before_prev: Option<&Token>, prev: &Token, token: &Token) -> bool {
+    use TokenType::*;
+
+    // A `-` with no operand before it is negation, and hugs its operand.
+    let unary = match prev.token_type {
+        Bang => true,
+        Minus => !before_prev.is_some_and(ends_expression),
+        _ => false,
+    };
+
+    match (prev.token_type, token.token_type) {
+        // Braces only meet other tokens on one line in maps, like `{"a": 1}`.
+        (_, RightParen | RightBracket | RightBrace | Comma | Semicolon | Dot) => false,
+        (LeftParen | LeftBracket | LeftBrace | Dot, _) => false,
+        // Calls, function declarations and indexing.
+        (Identifier | RightParen | RightBracket | RightBrace | This, LeftParen | LeftBracket) => false,
+        _ => !unary,
+    }
+}
@@ -1,381 +1,476 @@
-use crate::{environment::Environment, parser::{Expr, Stmt}, token::{Token, TokenType}, Lox};
+use std::{cell::RefCell, collections::HashMap, io::{self, Write}, rc::Rc};
+
+use crate::{
+    callable::{LoxCallable, LoxFunction},
+    class::{LoxClass, LoxInstance},
+    environment::Environment,
+    error::LoxError,
+    map::{LoxMap, MapKey},
+    natives,
+    parser::{Expr, FunctionDecl, Stmt},
+    token::{Span, Token, TokenType},
+    value::Value,
+};
+
+/// Why execution of a statement stopped early.
+pub enum Unwind {
+    /// A runtime error on its way up to `interpret`.
+    Error(LoxError),
+    /// A `return` statement carrying its value up to the enclosing call.
+    Return(Value),
+    /// A `break` on its way out of the innermost loop.
+    Break,
+    /// A `continue` on its way to the innermost loop's next iteration.
+    Continue,
+}
+
+impl From<LoxError> for Unwind {
+    fn from(error: LoxError) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+/// How deep calls may nest before `Stack overflow.`, the same as the VM.
+const MAX_CALL_DEPTH: usize = 1024;
 
 pub struct Interpreter {
-    environment: Environment,
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    output: Box<dyn Write>,
+    call_depth: usize,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        Self {
-            environment: Environment::new(),
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    /// An interpreter whose `print` statements write to `output`.
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        let mut environment = Environment::new();
+        for native in natives::all() {
+            environment.define(native.name.to_string(), Value::Native(Rc::new(native)));
         }
+
+        let globals = Rc::new(RefCell::new(environment));
+        Self { environment: Rc::clone(&globals), globals, output, call_depth: 0 }
     }
 
-    pub fn interpret(&mut self, statements: Vec<Stmt>) -> Option<()> {
-        for stmt in statements {
-            let res = self.execute(stmt);
-            if let None = res {
-                return None;
+    /// The global variables defined so far, natives excluded, sorted by name.
+    pub fn globals(&self) -> Vec<(String, Value)> {
+        let mut globals: Vec<(String, Value)> = self.globals
+            .borrow()
+            .bindings()
+            .into_iter()
+            .filter(|(_, value)| !matches!(value, Value::Native(_)))
+            .collect();
+        globals.sort_by(|a, b| a.0.cmp(&b.0));
+        globals
+    }
+
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.globals.borrow_mut().define(name.to_string(), value);
+    }
+
+    pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<(), LoxError> {
+        for stmt in &statements {
+            match self.execute(stmt) {
+                Ok(()) => {},
+                Err(Unwind::Error(error)) => return Err(error),
+                Err(Unwind::Return(_)) => break,
+                Err(Unwind::Break | Unwind::Continue) => unreachable!("the resolver rejects jumps outside loops"),
             }
         }
 
-        Some(())
+        Ok(())
     }
 
-    fn execute(&mut self, expr: Stmt) -> Option<()> {
-        match expr {
+    fn execute(&mut self, stmt: &Stmt) -> Result<(), Unwind> {
+        match stmt {
             Stmt::Expression(e) => {
-                let result = self.evaluate(*e);
-                if let None = result {
-                    return None;
-                }
-                return Some(());
-
+                self.evaluate(e)?;
+                Ok(())
             },
-            Stmt::Print(e) => {
-                let res = self.evaluate(*e);
-                if let None = res {
-                    return None;
-                }
-
-                println!("{}", res.unwrap());
+            Stmt::Print(e, span) => {
+                let res = self.evaluate(e)?;
 
-                return Some(())
-            },
-            Stmt::Let(op, expr) => {
-                let res = self.let_statement(op, expr);
-                if let None = res {
-                    return None;
+                if let Err(error) = writeln!(self.output, "{}", res) {
+                    // Only the span is known here; diagnostics find the line from it.
+                    let message = format!("Could not write output: {}.", error);
+                    return Err(LoxError::Runtime { line: 0, token: None, span: Some(*span), message }.into());
                 }
 
-                return Some(())
+                Ok(())
             },
-            Stmt::Block(statements) => {
-                let env = Environment::with_enclosing(self.environment.clone());
-                let res = self.execute_block(statements, env);
-                if let None = res {
-                    return None;
-                }
-                Some(())
+            Stmt::Let(op, expr) => self.let_statement(op, expr),
+            Stmt::Block(statements, _) => {
+                let env = Environment::with_enclosing(Rc::clone(&self.environment));
+                self.execute_block(statements, env)
             },
-            Stmt::If(cond, then, else_) => {
-                let res = self.if_statement(cond, *then, *else_);
-                if let None = res {
-                    return None;
-                }
-                Some(())
+            Stmt::If(cond, then, else_, _) => self.if_statement(cond, then, else_),
+            Stmt::While(cond, body, increment, _) => self.while_statement(cond, body, increment.as_deref()),
+            Stmt::Function(declaration) => {
+                let function = LoxFunction::new(
+                    Rc::new(declaration.clone()),
+                    Rc::clone(&self.environment),
+                    false
+                );
+                self.environment.borrow_mut().define(declaration.name.lexeme.clone(), Value::Function(Rc::new(function)));
+                Ok(())
             },
-            Stmt::While(cond, body) => {
-                let res = self.while_statement(cond, *body);
-                if let None = res {
-                    return None;
+            Stmt::Class(name, superclass, methods) => self.class_declaration(name, superclass, methods),
+            Stmt::Return(_, value) => {
+                let mut result = Value::Nil;
+                if let Some(value) = value {
+                    result = self.evaluate(value)?;
                 }
 
-                Some(())
-            }
+                Err(Unwind::Return(result))
+            },
+            Stmt::Break(_) => Err(Unwind::Break),
+            Stmt::Continue(_) => Err(Unwind::Continue),
         }
     }
 
-    fn while_statement(&mut self, condition: Expr, body: Stmt) -> Option<()> {
-        while Self::is_truthy(self.evaluate(condition.clone()).unwrap()) {
-            self.execute(body.clone());
+    fn class_declaration(
+        &mut self,
+        name: &Token,
+        superclass: &Option<Expr>,
+        methods: &[FunctionDecl]
+    ) -> Result<(), Unwind> {
+        let mut parent = None;
+        if let Some(superclass) = superclass {
+            let Value::Class(class) = self.evaluate(superclass)? else {
+                let Expr::Variable(superclass_name, _) = superclass else { unreachable!() };
+                return Err(LoxError::runtime(superclass_name, "Superclass must be a class.").into());
+            };
+            parent = Some(class);
+        }
+
+        self.environment.borrow_mut().define(name.lexeme.clone(), Value::Nil);
+
+        // Methods of a subclass close over a scope that binds `super`.
+        let mut closure = Rc::clone(&self.environment);
+        if let Some(parent) = &parent {
+            let mut env = Environment::with_enclosing(Rc::clone(&closure));
+            env.define("super".to_string(), Value::Class(Rc::clone(parent)));
+            closure = Rc::new(RefCell::new(env));
         }
 
-        return Some(())
+        let mut class_methods = HashMap::new();
+        for method in methods {
+            let function = LoxFunction::new(
+                Rc::new(method.clone()),
+                Rc::clone(&closure),
+                method.name.lexeme == "init"
+            );
+            class_methods.insert(method.name.lexeme.clone(), Value::Function(Rc::new(function)));
+        }
+
+        let class = LoxClass::new(name.lexeme.clone(), parent, class_methods);
+        self.environment.borrow_mut().assign(name, Value::Class(Rc::new(class)))?;
+        Ok(())
     }
 
-    fn if_statement(&mut self, condition: Expr, then: Stmt, else_: Option<Stmt>) -> Option<()> {
-        let cond = self.evaluate(condition);
-        if let None = cond {
-            return None;
+    fn while_statement(&mut self, condition: &Expr, body: &Stmt, increment: Option<&Expr>) -> Result<(), Unwind> {
+        while self.evaluate(condition)?.is_truthy() {
+            match self.execute(body) {
+                Ok(()) | Err(Unwind::Continue) => {},
+                Err(Unwind::Break) => break,
+                Err(unwind) => return Err(unwind),
+            }
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
         }
-        if Self::is_truthy(cond.unwrap()) {
+
+        Ok(())
+    }
+
+    fn if_statement(&mut self, condition: &Expr, then: &Stmt, else_: &Option<Stmt>) -> Result<(), Unwind> {
+        let cond = self.evaluate(condition)?;
+        if cond.is_truthy() {
             return self.execute(then);
-        } else if else_.is_some() {
-            return self.execute(else_.unwrap());
+        } else if let Some(else_) = else_ {
+            return self.execute(else_);
         }
-        Some(())
+        Ok(())
     }
 
-    fn execute_block(&mut self, statements: Vec<Box<Stmt>>, env: Environment) -> Option<()> {
-        self.environment = env.clone();
-        let mut cur = env.clone();
+    /// Runs `statements` inside `env`, then switches back to the current
+    /// environment however the block finished.
+    pub fn execute_block(&mut self, statements: &[Stmt], env: Environment) -> Result<(), Unwind> {
+        let previous = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(env)));
 
+        let mut result = Ok(());
         for stmt in statements {
-            let res = self.execute(*stmt);
-            if let None = res {
-                return None;
+            result = self.execute(stmt);
+            if result.is_err() {
+                break;
             }
-            cur = *self.environment.enclosing.clone().unwrap();
         }
 
-        self.environment = cur;
-        Some(())
+        self.environment = previous;
+        result
     }
 
-    fn let_statement(&mut self, token: Token, expr: Option<Box<Expr>>) -> Option<()> {
-        let mut value = "nil".to_string();
+    fn let_statement(&mut self, token: &Token, expr: &Option<Box<Expr>>) -> Result<(), Unwind> {
+        let mut value = Value::Nil;
         if let Some(init_val) = expr {
-            value = self.evaluate(*init_val).unwrap_or("nil".to_string());
+            value = self.evaluate(init_val)?;
         }
 
-        self.environment.define(token.lexeme, value);
-        return Some(())
+        self.environment.borrow_mut().define(token.lexeme.clone(), value);
+        Ok(())
     }
 
-    fn evaluate(&mut self, expr: Expr) -> Option<String> {
+    /// Evaluates `expr` in the current scope. At the top level, this is how
+    /// the REPL gets the value of a lone expression to echo.
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value, LoxError> {
         match expr {
             Expr::Binary(left, op, right) => self.eval_binary(left, op, right),
-            Expr::Literal(lit) => self.eval_literal(lit),
-            Expr::Grouping(expression) => self.eval_group(expression),
+            Expr::Literal(lit, _) => Ok(lit.clone()),
+            Expr::Grouping(expression, _) => self.evaluate(expression),
             Expr::Unary(op, right) => self.eval_unary(op, right),
-            Expr::Ternary(cond, left, right) => self.eval_ternary(*cond, *left, *right),
-            Expr::Variable(var) => {
-                let value = self.environment.get(var);
-                if let None = value {
-                    return None;
+            Expr::Ternary(cond, left, right) => self.eval_ternary(cond, left, right),
+            Expr::Variable(var, depth) => self.look_up_variable(var, *depth),
+            Expr::Assign(name, expr, depth) => {
+                let value = self.evaluate(expr)?;
+                match depth {
+                    Some(distance) => Environment::assign_at(&self.environment, *distance, name, value.clone()),
+                    None => self.globals.borrow_mut().assign(name, value.clone())?,
                 }
-                return value;
+                Ok(value)
             },
-            Expr::Assign(name, expr) => {
-                let value = self.evaluate(*expr);
-                if let None = value.clone() {
-                    return None;
+            Expr::Logical(left, op, right) => self.eval_logical(left, op, right),
+            Expr::Call(callee, paren, arguments) => self.eval_call(callee, paren, arguments),
+            Expr::Get(object, name) => {
+                match self.evaluate(object)? {
+                    Value::Instance(instance) => LoxInstance::get(&instance, name),
+                    _ => Err(LoxError::runtime_at(name, object.span(), "Only instances have properties.")),
                 }
-                self.environment.assign(
-                    name,
-                    value.clone().unwrap_or("nil".to_string())
-                );
-                return value;
             },
-            Expr::Logical(left, op, right) => {
-                let res = self.eval_logical(*left, op, *right);
-                if let None = res {
-                    return None;
+            Expr::Set(object, name, value) => {
+                // The value runs before the receiver is checked, as the VM
+                // only checks once both are on the stack.
+                let object_value = self.evaluate(object)?;
+                let value = self.evaluate(value)?;
+                let Value::Instance(instance) = object_value else {
+                    return Err(LoxError::runtime_at(name, object.span(), "Only instances have fields."));
+                };
+
+                instance.borrow_mut().set(&name.lexeme, value.clone());
+                Ok(value)
+            },
+            Expr::This(keyword, depth) => self.look_up_variable(keyword, *depth),
+            Expr::Super(_, method, depth) => {
+                let Some(distance) = depth else {
+                    unreachable!("the resolver gives every 'super' a depth, or reports it.")
+                };
+                self.eval_super(method, *distance)
+            },
+            Expr::List(items, _) => {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    values.push(self.evaluate(item)?);
+                }
+                Ok(Value::list(values))
+            },
+            Expr::Map(entries, _) => {
+                // Every entry is evaluated before any key is checked, as the
+                // VM builds the map only once they are all on the stack.
+                let mut pairs = Vec::with_capacity(entries.len());
+                for (key, _, value) in entries {
+                    pairs.push((self.evaluate(key)?, self.evaluate(value)?));
                 }
 
-                return Some(res.unwrap());
+                let mut map = LoxMap::new();
+                for ((key, value), (_, colon, _)) in pairs.into_iter().zip(entries) {
+                    // The VM only knows which literal failed, so point at all of it.
+                    let key = MapKey::new(&key).map_err(|message| LoxError::runtime_at(colon, expr.span(), message))?;
+                    map.insert(key, value);
+                }
+                Ok(Value::map(map))
+            },
+            Expr::Index(object_expr, bracket, index) => {
+                let object = self.evaluate(object_expr)?;
+                let index = self.evaluate(index)?;
+                object.get_index(&index).map_err(|message| LoxError::runtime_at(bracket, expr.span(), message))
+            },
+            Expr::SetIndex(object_expr, bracket, index, value) => {
+                let object = self.evaluate(object_expr)?;
+                let index = self.evaluate(index)?;
+                // The value may change the list's length, so it comes first.
+                let value = self.evaluate(value)?;
+                object.set_index(&index, value.clone())
+                    .map_err(|message| LoxError::runtime_at(bracket, expr.span(), message))?;
+                Ok(value)
             },
         }
     }
 
-    fn eval_logical(
-        &mut self,
-        left: Expr,
-        operator: Token,
-        right: Expr
-    ) -> Option<String> {
-        let left = self.evaluate(left);
-        if let None = left {
-            return None;
-        }
-        let left = left.unwrap();
-
-        if operator.token_type == TokenType::Or {
-            if Self::is_truthy(left.clone()) {
-                return Some(left);
-            }
-        } else {
-            if !Self::is_truthy(left.clone()) {
-                return Some(left);
-            }
+    fn eval_super(&mut self, method: &Token, distance: usize) -> Result<Value, LoxError> {
+        let superclass = Environment::ancestor(&self.environment, distance)
+            .borrow()
+            .get_local("super");
+        let object = Environment::ancestor(&self.environment, distance - 1)
+            .borrow()
+            .get_local("this");
+
+        let (Some(Value::Class(superclass)), Some(Value::Instance(object))) = (superclass, object) else {
+            unreachable!("the resolver binds 'super' and 'this' around every subclass method.")
+        };
+
+        match superclass.find_method(&method.lexeme) {
+            Some(Value::Function(found)) => Ok(Value::Function(Rc::new(found.bind(object)))),
+            _ => Err(LoxError::runtime(method, format!("Undefined property '{}'.", method.lexeme))),
         }
-
-        return self.evaluate(right);
     }
 
-    fn eval_binary(
-        &mut self, left: Box<Expr>,
-        op: Token, right: Box<Expr>
-    ) -> Option<String> {
-        let left = self.evaluate(*left);
-        if let None = left {
-            return None;
+    fn look_up_variable(&self, name: &Token, depth: Option<usize>) -> Result<Value, LoxError> {
+        match depth {
+            Some(distance) => Environment::get_at(&self.environment, distance, name),
+            None => self.globals.borrow().get(name),
         }
-        let left = left.unwrap();
-        let right = self.evaluate(*right);
-        if let None = right {
-            return None;
-        }
-        let right = right.unwrap();
-
-        match op.token_type {
-            TokenType::Greater => {
-                if let None = Self::check_number_operands(op, left.clone(), right.clone()) {
-                    return None;
-                }
-                let left = left.parse::<f64>().unwrap();
-                let right = right.parse::<f64>().unwrap();
+    }
 
-                return Some((left > right).to_string());
-            },
-            TokenType::GreaterEqual => {
-                if let None = Self::check_number_operands(op, left.clone(), right.clone()) {
-                    return None;
-                }
-                let left = left.parse::<f64>().unwrap();
-                let right = right.parse::<f64>().unwrap();
+    fn eval_call(&mut self, callee_expr: &Expr, paren: &Token, arguments: &[Expr]) -> Result<Value, LoxError> {
+        let callee = self.evaluate(callee_expr)?;
 
-                return Some((left >= right).to_string());
-            },
-            TokenType::Less => {
-                if let None = Self::check_number_operands(op, left.clone(), right.clone()) {
-                    return None;
-                }
-                let left = left.parse::<f64>().unwrap();
-                let right = right.parse::<f64>().unwrap();
+        let mut args = Vec::new();
+        for argument in arguments {
+            args.push(self.evaluate(argument)?);
+        }
 
-                return Some((left < right).to_string());
-            },
-            TokenType::LessEqual => {
-                if let None = Self::check_number_operands(op, left.clone(), right.clone()) {
-                    return None;
-                }
-                let left = left.parse::<f64>().unwrap();
-                let right = right.parse::<f64>().unwrap();
+        let function: Rc<dyn LoxCallable> = match callee {
+            Value::Function(function) => function,
+            Value::Native(native) => native,
+            Value::Class(class) => class,
+            _ => return Err(LoxError::runtime_at(paren, callee_expr.span(), "Can only call functions and classes.")),
+        };
+
+        if args.len() != function.arity() {
+            return Err(LoxError::runtime(
+                paren,
+                format!("Expected {} arguments but got {}.", function.arity(), args.len())
+            ));
+        }
 
-                return Some((left <= right).to_string());
-            },
-            TokenType::BangEqual => {
-                return Some((!Self::is_equals(left, right)).to_string());
-            },
-            TokenType::EqualEqual => {
-                return Some(Self::is_equals(left, right).to_string());
-            },
-            TokenType::Minus => {
-                if let None = Self::check_number_operands(op, left.clone(), right.clone()) {
-                    return None;
-                }
-                let left = left.parse::<f64>().unwrap();
-                let right = right.parse::<f64>().unwrap();
+        // Each call nests several Rust frames, so deep recursion has to be
+        // caught before it overflows the real stack.
+        if self.call_depth == MAX_CALL_DEPTH {
+            return Err(LoxError::runtime(paren, "Stack overflow."));
+        }
+        self.call_depth += 1;
+        let result = function.call(self, paren, args);
+        self.call_depth -= 1;
+        result
+    }
 
-                return Some((left - right).to_string());
-            },
+    fn eval_logical(
+        &mut self,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr
+    ) -> Result<Value, LoxError> {
+        let left = self.evaluate(left)?;
 
-            TokenType::Slash => {
-                if let None = Self::check_number_operands(op, left.clone(), right.clone()) {
-                    return None;
-                }
-                let left = left.parse::<f64>().unwrap();
-                let right = right.parse::<f64>().unwrap();
+        if operator.token_type == TokenType::Or {
+            if left.is_truthy() {
+                return Ok(left);
+            }
+        } else if !left.is_truthy() {
+            return Ok(left);
+        }
 
-                return Some((left / right).to_string());
-            },
+        self.evaluate(right)
+    }
 
-            TokenType::Star => {
-                if let None = Self::check_number_operands(op, left.clone(), right.clone()) {
-                    return None;
-                }
-                let left = left.parse::<f64>().unwrap();
-                let right = right.parse::<f64>().unwrap();
+    fn eval_binary(
+        &mut self, left: &Expr,
+        op: &Token, right: &Expr
+    ) -> Result<Value, LoxError> {
+        let span = left.span().to(right.span());
+        let left = self.evaluate(left)?;
+        let right = self.evaluate(right)?;
 
-                return Some((left * right).to_string());
-            },
+        match op.token_type {
+            TokenType::BangEqual => return Ok(Value::Bool(left != right)),
+            TokenType::EqualEqual => return Ok(Value::Bool(left == right)),
             TokenType::Plus => {
-                if Self::is_number(&left) && Self::is_number(&right) {
-                    let left = left.parse::<f64>().unwrap();
-                    let right = right.parse::<f64>().unwrap();
-
-                    return Some((left + right).to_string());
+                match (&left, &right) {
+                    (Value::Number(l), Value::Number(r)) => {
+                        return Ok(Value::Number(l + r));
+                    },
+                    (Value::String(_), _) | (_, Value::String(_)) => {
+                        return Ok(Value::String(format!("{}{}", left, right)));
+                    },
+                    _ => {}
                 }
 
-                if Self::is_alpha(&left) || Self::is_alpha(&right) {
-                    return Some(format!("{}{}", left, right));
-                }
-
-                Lox::report(op.line, op.lexeme, "Operands must be two numbers or two strings.".to_string());
-                return None;
-            }
-            _ => todo!()
+                return Err(LoxError::runtime_at(op, span, "Operands must be two numbers or two strings."));
+            },
+            _ => {}
         }
-    }
-
-    fn eval_literal(&mut self, literal: String) -> Option<String> {
-        Some(literal)
-    }
 
-    fn eval_group(&mut self, expr: Box<Expr>) -> Option<String> {
-        return self.evaluate(*expr);
+        let (left, right) = Self::check_number_operands(op, span, &left, &right)?;
+        match op.token_type {
+            TokenType::Greater => Ok(Value::Bool(left > right)),
+            TokenType::GreaterEqual => Ok(Value::Bool(left >= right)),
+            TokenType::Less => Ok(Value::Bool(left < right)),
+            TokenType::LessEqual => Ok(Value::Bool(left <= right)),
+            TokenType::Minus => Ok(Value::Number(left - right)),
+            TokenType::Slash => Ok(Value::Number(left / right)),
+            TokenType::Star => Ok(Value::Number(left * right)),
+            _ => unreachable!()
+        }
     }
 
-    fn eval_unary(&mut self, operator: Token, right: Box<Expr>) -> Option<String> {
-        let right = self.evaluate(*right).unwrap();
+    fn eval_unary(&mut self, operator: &Token, right: &Expr) -> Result<Value, LoxError> {
+        let span = operator.span.to(right.span());
+        let right = self.evaluate(right)?;
 
         match operator.token_type {
             TokenType::Minus => {
-                if let None = Self::check_number_operand(operator, right.clone()) {
-                    return None;
-                }
-                let right = right.parse::<f64>().unwrap();
+                let right = Self::check_number_operand(operator, span, &right)?;
 
-                Some((-right).to_string())
+                Ok(Value::Number(-right))
             },
             TokenType::Bang => {
-                Some((!Self::is_truthy(right)).to_string())
+                Ok(Value::Bool(!right.is_truthy()))
             },
             _ => unreachable!()
         }
     }
 
-    fn eval_ternary(&mut self, condition: Expr, left: Expr, right: Expr) -> Option<String> {
-        let condition = self.evaluate(condition).unwrap();
-        if Self::is_truthy(condition) {
-            return self.evaluate(left);
+    fn eval_ternary(&mut self, condition: &Expr, left: &Expr, right: &Expr) -> Result<Value, LoxError> {
+        let condition = self.evaluate(condition)?;
+        if condition.is_truthy() {
+            self.evaluate(left)
         } else {
-            return self.evaluate(right);
+            self.evaluate(right)
         }
     }
 
     fn check_number_operand(
-        operator: Token,
-        operand: String
-        ) -> Option<()> {
-        if operand.parse::<f64>().is_ok() { return Some(()); }
-
-        Lox::report(
-            operator.line,
-            format!("at '{}' ", operator.lexeme),
-            "Operand must be a number.".to_string()
-        );
-        return None;
-    }
-
-    fn check_number_operands(
-        operator: Token,
-        operand1: String,
-        operand2: String
-        ) -> Option<()> {
-        if operand1.parse::<f64>().is_ok() && operand2.parse::<f64>().is_ok()
-        { return Some(()); }
-
-        Lox::report(operator.line, operator.lexeme, "Operands must be number.".to_string());
-        return None;
-    }
-
-    fn is_equals(a: String, b: String) -> bool {
-        if a == "nil".to_string() && b == "nil".to_string() {
-            return true;
-        }
-
-        if a == "nil".to_string() {
-            return false;
-        }
-
-        return a == b;
-    }
-
-    fn is_truthy(object: String) -> bool {
-        if object == "nil".to_string() { return false; }
-        if object == "false".to_string() { return false; }
+        operator: &Token,
+        span: Span,
+        operand: &Value
+        ) -> Result<f64, LoxError> {
+        if let Value::Number(n) = operand { return Ok(*n); }
 
-        return true;
+        Err(LoxError::runtime_at(operator, span, "Operand must be a number."))
     }
 
-    fn is_number(string: &str) -> bool {
-        string.parse::<f64>().is_ok()
-    }
-
-    fn is_alpha(string: &str) -> bool {
-        string.parse::<f64>().is_err()
+    fn check_number_operands(
+        operator: &Token,
+        span: Span,
+        operand1: &Value,
+        operand2: &Value
+        ) -> Result<(f64, f64), LoxError> {
+        if let (Value::Number(a), Value::Number(b)) = (operand1, operand2)
+        { return Ok((*a, *b)); }
+
+        Err(LoxError::runtime_at(operator, span, "Operands must be number."))
     }
 }
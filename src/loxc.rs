@@ -0,0 +1,374 @@
+//! The `.loxc` file format: a compiled script the VM can run without
+//! scanning or parsing the source again.
+//!
+//! ```text
+//! file     := "LOXC" version:u16 function
+//! function := name:string arity:u32 upvalue_count:u32
+//!             constant_count:u32 constant*
+//!             code_len:u32 byte*
+//!             run_count:u32 (line:u32 run_len:u32)*
+//! constant := 0 number:f64 | 1 string | 2 function
+//! string   := len:u32 utf8-byte*
+//! ```
+//!
+//! Integers and floats are little-endian. The line table is run-length
+//! encoded: each run gives a source line and how many code bytes share it.
+//! Spans are left out, since there is no source to point them into, so
+//! runtime errors from a loaded script report only their line.
+//!
+//! A file may be damaged or written by hand, so loading checks the bytecode
+//! of every function before the VM is given any of it; see `verify`.
+
+use std::rc::Rc;
+
+use crate::chunk::{Chunk, Constant, FunctionProto, OpCode};
+
+pub const MAGIC: &[u8; 4] = b"LOXC";
+pub const VERSION: u16 = 1;
+
+const TAG_NUMBER: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_FUNCTION: u8 = 2;
+
+/// How deeply function constants may nest, so reading a file can't
+/// exhaust the native stack.
+const MAX_NESTING: usize = 256;
+
+pub fn is_loxc(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn serialize(function: &FunctionProto) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    write_function(&mut out, function);
+    out
+}
+
+pub fn deserialize(bytes: &[u8]) -> Result<FunctionProto, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("Not a .loxc file.".to_string());
+    }
+
+    let version = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+    if version != VERSION {
+        return Err(format!("Unsupported .loxc version {} (expected {}).", version, VERSION));
+    }
+
+    let function = reader.function(0)?;
+    if reader.pos != bytes.len() {
+        return Err("Unexpected trailing bytes after the script.".to_string());
+    }
+    // The VM calls the script with no arguments, from a closure with no
+    // upvalues.
+    if function.arity != 0 || function.upvalue_count != 0 {
+        return Err("Invalid bytecode: the script can't have parameters or upvalues.".to_string());
+    }
+    verify(&function)?;
+    Ok(function)
+}
+
+fn write_function(out: &mut Vec<u8>, function: &FunctionProto) {
+    write_string(out, &function.name);
+    write_u32(out, function.arity);
+    write_u32(out, function.upvalue_count);
+
+    let chunk = &function.chunk;
+    write_u32(out, chunk.constants.len());
+    for constant in &chunk.constants {
+        match constant {
+            Constant::Number(n) => {
+                out.push(TAG_NUMBER);
+                out.extend_from_slice(&n.to_le_bytes());
+            },
+            Constant::String(s) => {
+                out.push(TAG_STRING);
+                write_string(out, s);
+            },
+            Constant::Function(function) => {
+                out.push(TAG_FUNCTION);
+                write_function(out, function);
+            },
+        }
+    }
+
+    write_u32(out, chunk.code.len());
+    out.extend_from_slice(&chunk.code);
+
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for &line in &chunk.lines {
+        match runs.last_mut() {
+            Some((last, count)) if *last == line => *count += 1,
+            _ => runs.push((line, 1)),
+        }
+    }
+    write_u32(out, runs.len());
+    for (line, count) in runs {
+        write_u32(out, line);
+        write_u32(out, count);
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    write_u32(out, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.bytes.len());
+        let Some(end) = end else {
+            return Err("Unexpected end of .loxc file.".to_string());
+        };
+
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()?;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| "Invalid UTF-8 in .loxc string.".to_string())
+    }
+
+    fn function(&mut self, depth: usize) -> Result<FunctionProto, String> {
+        if depth > MAX_NESTING {
+            return Err("Functions are nested too deeply.".to_string());
+        }
+
+        let name = self.string()?;
+        let arity = self.u32()?;
+        let upvalue_count = self.u32()?;
+
+        let mut chunk = Chunk::new();
+        let constant_count = self.u32()?;
+        for _ in 0..constant_count {
+            let constant = match self.u8()? {
+                TAG_NUMBER => Constant::Number(f64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+                TAG_STRING => Constant::String(self.string()?),
+                TAG_FUNCTION => Constant::Function(Rc::new(self.function(depth + 1)?)),
+                tag => return Err(format!("Unknown constant tag {}.", tag)),
+            };
+            chunk.constants.push(constant);
+        }
+
+        let code_len = self.u32()?;
+        chunk.code = self.take(code_len)?.to_vec();
+
+        let run_count = self.u32()?;
+        for _ in 0..run_count {
+            let line = self.u32()?;
+            let count = self.u32()?;
+            if chunk.lines.len() + count > code_len {
+                return Err("Line table does not match the code length.".to_string());
+            }
+            chunk.lines.extend(std::iter::repeat_n(line, count));
+        }
+        if chunk.lines.len() != chunk.code.len() {
+            return Err("Line table does not match the code length.".to_string());
+        }
+
+        Ok(FunctionProto { name, arity, upvalue_count, chunk })
+    }
+}
+
+/// Checks that `function` and the functions nested in it only read
+/// operands, constants, upvalues and stack slots that exist, only jump to
+/// the start of an instruction, never run past the end of their code, and
+/// leave the stack the same height however an instruction is reached. The
+/// compiler's output always passes, so the VM doesn't check these itself.
+fn verify(function: &FunctionProto) -> Result<(), String> {
+    let instructions = decode(function)
+        .map_err(|message| format!("Invalid bytecode in '{}': {}", function.name, message))?;
+    check_stack(function, &instructions)
+        .map_err(|message| format!("Invalid bytecode in '{}': {}", function.name, message))?;
+
+    for constant in &function.chunk.constants {
+        if let Constant::Function(nested) = constant {
+            verify(nested)?;
+        }
+    }
+    Ok(())
+}
+
+/// An instruction whose operands have been checked against the chunk.
+struct Instruction {
+    offset: usize,
+    op: OpCode,
+    /// The one-byte operand, or the two-byte one for jumps and counts.
+    operand: usize,
+    /// Where the next instruction starts.
+    next: usize,
+    /// For `Class`, whether it has a superclass; for `Closure`, the local
+    /// slots it captures.
+    extra: Vec<usize>,
+}
+
+fn decode(function: &FunctionProto) -> Result<Vec<Instruction>, String> {
+    let chunk = &function.chunk;
+    let code = &chunk.code;
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+
+    while offset < code.len() {
+        let op = OpCode::from_byte(code[offset])
+            .ok_or_else(|| format!("unknown opcode {} at {}.", code[offset], offset))?;
+        let operand_bytes = |len: usize| {
+            code.get(offset + 1..offset + 1 + len)
+                .ok_or_else(|| format!("operands of {:?} at {} run past the end of the code.", op, offset))
+        };
+        let string_constant = |index: usize| match chunk.constants.get(index) {
+            Some(Constant::String(_)) => Ok(()),
+            _ => Err(format!("{:?} at {} needs a string constant.", op, offset)),
+        };
+
+        let mut instruction = Instruction { offset, op, operand: 0, next: offset + 1, extra: Vec::new() };
+        match op {
+            OpCode::GetLocal | OpCode::SetLocal | OpCode::GetUpvalue | OpCode::SetUpvalue | OpCode::Call => {
+                instruction.operand = operand_bytes(1)?[0] as usize;
+                instruction.next += 1;
+                let upvalue = matches!(op, OpCode::GetUpvalue | OpCode::SetUpvalue);
+                if upvalue && instruction.operand >= function.upvalue_count {
+                    return Err(format!("{:?} at {} reads a missing upvalue.", op, offset));
+                }
+            },
+            OpCode::Constant | OpCode::GetGlobal | OpCode::DefineGlobal | OpCode::SetGlobal
+            | OpCode::GetProperty | OpCode::SetProperty | OpCode::GetSuper | OpCode::Jump
+            | OpCode::JumpIfFalse | OpCode::Loop | OpCode::BuildList | OpCode::BuildMap => {
+                let bytes = operand_bytes(2)?;
+                instruction.operand = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+                instruction.next += 2;
+                match op {
+                    OpCode::Constant => match chunk.constants.get(instruction.operand) {
+                        Some(Constant::Number(_) | Constant::String(_)) => {},
+                        _ => return Err(format!("Constant at {} needs a number or string constant.", offset)),
+                    },
+                    OpCode::GetGlobal | OpCode::DefineGlobal | OpCode::SetGlobal
+                    | OpCode::GetProperty | OpCode::SetProperty | OpCode::GetSuper => {
+                        string_constant(instruction.operand)?;
+                    },
+                    _ => {},
+                }
+            },
+            OpCode::Class => {
+                let bytes = operand_bytes(4)?;
+                string_constant(u16::from_be_bytes([bytes[0], bytes[1]]) as usize)?;
+                instruction.operand = bytes[2] as usize;
+                instruction.extra.push((bytes[3] == 1) as usize);
+                instruction.next += 4;
+            },
+            OpCode::Closure => {
+                let bytes = operand_bytes(2)?;
+                let Some(Constant::Function(nested)) = chunk.constants.get(u16::from_be_bytes([bytes[0], bytes[1]]) as usize) else {
+                    return Err(format!("Closure at {} needs a function constant.", offset));
+                };
+                let captures = operand_bytes(2 + nested.upvalue_count * 2)?[2..].to_vec();
+                for capture in captures.chunks(2) {
+                    let (is_local, index) = (capture[0] == 1, capture[1] as usize);
+                    if is_local {
+                        instruction.extra.push(index);
+                    } else if index >= function.upvalue_count {
+                        return Err(format!("Closure at {} captures a missing upvalue.", offset));
+                    }
+                }
+                instruction.next += 2 + nested.upvalue_count * 2;
+            },
+            _ => {},
+        }
+
+        offset = instruction.next;
+        instructions.push(instruction);
+    }
+    Ok(instructions)
+}
+
+/// Follows every path through the code with the height the stack has
+/// there, counting the function's own slot and its parameters.
+fn check_stack(function: &FunctionProto, instructions: &[Instruction]) -> Result<(), String> {
+    let len = function.chunk.code.len();
+    let mut index_at = vec![None; len];
+    for (i, instruction) in instructions.iter().enumerate() {
+        index_at[instruction.offset] = Some(i);
+    }
+
+    let mut heights: Vec<Option<usize>> = vec![None; instructions.len()];
+    let mut pending = vec![(0, function.arity + 1)];
+    while let Some((offset, height)) = pending.pop() {
+        let Some(i) = index_at.get(offset).copied().flatten() else {
+            return Err(format!("a jump or the last instruction leads to {}, which isn't an instruction.", offset));
+        };
+        match heights[i] {
+            Some(seen) if seen == height => continue,
+            Some(seen) => {
+                return Err(format!("the stack is {} or {} values high at {}.", seen, height, offset));
+            },
+            None => heights[i] = Some(height),
+        }
+
+        let instruction = &instructions[i];
+        let (op, operand, next) = (instruction.op, instruction.operand, instruction.next);
+        let (pops, pushes) = match op {
+            OpCode::Constant | OpCode::Nil | OpCode::True | OpCode::False | OpCode::GetGlobal
+            | OpCode::GetUpvalue => (0, 1),
+            OpCode::GetLocal => (0, 1),
+            OpCode::Pop | OpCode::DefineGlobal | OpCode::Print | OpCode::CloseUpvalue => (1, 0),
+            OpCode::SetLocal | OpCode::SetGlobal | OpCode::SetUpvalue | OpCode::GetProperty
+            | OpCode::Not | OpCode::Negate | OpCode::JumpIfFalse => (1, 1),
+            OpCode::SetProperty | OpCode::GetSuper | OpCode::Equal | OpCode::Greater | OpCode::Less
+            | OpCode::Add | OpCode::Subtract | OpCode::Multiply | OpCode::Divide | OpCode::GetIndex => (2, 1),
+            OpCode::SetIndex => (3, 1),
+            OpCode::Jump | OpCode::Loop => (0, 0),
+            OpCode::Call => (operand + 1, 1),
+            OpCode::Closure => (0, 1),
+            OpCode::Return => (1, 0),
+            OpCode::BuildList => (operand, 1),
+            OpCode::BuildMap => (operand * 2, 1),
+            OpCode::Class => (operand + instruction.extra[0], 1 + instruction.extra[0]),
+        };
+        if pops > height {
+            return Err(format!("{:?} at {} takes more values than the stack holds.", op, instruction.offset));
+        }
+        let slots = match op {
+            OpCode::GetLocal | OpCode::SetLocal => vec![operand],
+            OpCode::Closure => instruction.extra.clone(),
+            _ => Vec::new(),
+        };
+        if slots.iter().any(|&slot| slot >= height) {
+            return Err(format!("{:?} at {} uses a local slot the stack doesn't have.", op, instruction.offset));
+        }
+
+        let after = height - pops + pushes;
+        match op {
+            OpCode::Return => {},
+            OpCode::Jump => pending.push((next + operand, after)),
+            OpCode::JumpIfFalse => pending.extend([(next, after), (next + operand, after)]),
+            OpCode::Loop => match next.checked_sub(operand) {
+                Some(target) => pending.push((target, after)),
+                None => return Err(format!("Loop at {} jumps before the start of the code.", instruction.offset)),
+            },
+            _ => pending.push((next, after)),
+        }
+    }
+    Ok(())
+}
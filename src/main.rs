@@ -1,22 +1,72 @@
-use std::{fs, io::{self, BufRead, Write}};
+use std::{fs, io, rc::Rc};
 
+use cli::{AstFormat, Command, Script};
+use compiler::Compiler;
+use editor::Editor;
+use error::LoxError;
 use interpreter::Interpreter;
-use parser::Parser;
+use parser::{Expr, Parser, Stmt};
+use resolver::Resolver;
+use value::Value;
+use vm::Vm;
 
-use crate::scanner::Scanner;
+use scanner::Scanner;
+use token::Token;
 
+mod ast_json;
+mod callable;
+mod chunk;
+mod cli;
+mod class;
+mod compiler;
+mod diagnostic;
+mod editor;
 mod environment;
+mod formatter;
+mod error;
 mod interpreter;
+mod json;
+mod loxc;
+mod map;
+mod natives;
 mod parser;
+mod repl;
+mod resolver;
 mod scanner;
+mod sexpr;
 mod tests;
 mod token;
+mod value;
+mod vm;
+
+/// Which engine runs parsed programs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// Walks the syntax tree directly.
+    Interpreter,
+    /// Compiles to bytecode and runs it on a stack machine.
+    Vm,
+}
 
 pub struct Lox {
     had_error: bool,
     had_runtime_error: bool,
-    extract_ast: bool,
-    target_file: Option<String>,
+    backend: Backend,
+    /// Where to write compiled bytecode instead of running the script.
+    compile_target: Option<String>,
+    /// The name of what is running.
+    file_name: String,
+    /// Everything run so far, end to end, for rendering diagnostics. A REPL
+    /// keeps all of it, since a function defined by an earlier input can
+    /// still fail.
+    source: String,
+    /// Where each input starts in `source`, and its name.
+    inputs: Vec<(usize, String)>,
+    /// The arguments after the script, given to it as the `args` global.
+    args: Vec<String>,
+    /// Kept across calls to `run` so a REPL session remembers its state.
+    interpreter: Interpreter,
+    vm: Vm,
 }
 
 impl Lox {
@@ -24,102 +74,455 @@ impl Lox {
         Self {
             had_error: false,
             had_runtime_error: false,
-            extract_ast: false,
-            target_file: None,
+            backend: Backend::Interpreter,
+            compile_target: None,
+            file_name: "<repl>".to_string(),
+            source: String::new(),
+            inputs: Vec::new(),
+            args: Vec::new(),
+            interpreter: Interpreter::new(),
+            vm: Vm::new(),
         }
     }
+
+    /// Sets the script's arguments and starts a fresh session with them.
+    fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+        self.reset();
+    }
+
+    /// Forgets every global the program defined, keeping natives and `args`.
+    fn reset(&mut self) {
+        let args = || Value::list(self.args.iter().cloned().map(Value::String).collect());
+        self.interpreter = Interpreter::new();
+        self.interpreter.define_global("args", args());
+        self.vm = Vm::new();
+        self.vm.define_global("args", args());
+    }
     fn run_prompt(&mut self) {
+        let mut editor = Editor::new(editor::history_file());
+        // Lines typed so far for a statement that isn't finished yet.
+        let mut source = String::new();
         loop {
-            let mut source = String::new();
-            print!(">>> ");
-            io::stdout().flush().expect("Error flushing stdout.");
-            let input = io::stdin()
-                .lock()
-                .read_line(&mut source)
-                .expect("Error reading from stdin.");
-
-            if input == 0 {
-                return;
+            let prompt = if source.is_empty() { ">>> " } else { "... " };
+            let line = match editor.read_line(prompt, &self.completion_names()) {
+                Ok(Some(line)) => line,
+                Ok(None) => return,
+                // Ctrl-C throws away the statement being typed.
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    source.clear();
+                    continue;
+                },
+                Err(e) => {
+                    eprintln!("Error reading from stdin: {}", e);
+                    return;
+                },
+            };
+
+            if source.is_empty() {
+                if let Some(command) = line.trim().strip_prefix(':') {
+                    self.meta_command(command);
+                    continue;
+                }
+            }
+
+            // A blank line runs whatever has been typed, finished or not.
+            let blank = line.trim().is_empty();
+            source.push_str(&line);
+            source.push('\n');
+            if !blank && repl::is_incomplete(&source) {
+                continue;
             }
 
-            self.run(source);
+            self.file_name = "<repl>".to_string();
+            self.run(std::mem::take(&mut source), true);
             self.had_error = false;
+            self.had_runtime_error = false;
         }
     }
 
-    fn run_file(&mut self, path: String) {
-        let source = std::fs::read_to_string(path);
-        if let Err(e) = &source {
-            eprintln!("{}", e.to_string());
-            return;
+    /// What tab completes to at the prompt: keywords, natives and the
+    /// globals defined in the session.
+    fn completion_names(&self) -> Vec<String> {
+        let globals = match self.backend {
+            Backend::Interpreter => self.interpreter.globals(),
+            Backend::Vm => self.vm.globals(),
+        };
+
+        scanner::KEYWORDS.iter()
+            .map(|(name, _)| name.to_string())
+            .chain(natives::all().into_iter().map(|native| native.name.to_string()))
+            .chain(globals.into_iter().map(|(name, _)| name))
+            .collect()
+    }
+
+    /// Handles a REPL line starting with `:`.
+    fn meta_command(&mut self, command: &str) {
+        let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+        match (name, argument.trim()) {
+            ("reset", "") => {
+                self.reset();
+                println!("Session reset.");
+            },
+            ("env", "") => {
+                let globals = match self.backend {
+                    Backend::Interpreter => self.interpreter.globals(),
+                    Backend::Vm => self.vm.globals(),
+                };
+                for (name, value) in globals {
+                    println!("{} = {}", name, value);
+                }
+            },
+            ("load", "") => eprintln!("Usage: :load <file>"),
+            ("load", path) => match fs::read_to_string(path) {
+                Ok(source) => {
+                    self.file_name = path.to_string();
+                    self.run(source, false);
+                },
+                Err(e) => eprintln!("{}: {}", path, e),
+            },
+            _ => eprintln!("Unknown command ':{}'. Try :reset, :env or :load <file>.", command),
         }
-        let source = source.unwrap();
+    }
 
-        self.run(source);
+    /// Runs a script, either source or a `.loxc` file.
+    fn run_file(&mut self, script: &Script) {
+        let bytes = self.load(script);
+        if loxc::is_loxc(&bytes) {
+            if self.compile_target.is_some() {
+                eprintln!("rlox: {}: --compile needs Lox source, not a compiled script\nTry 'rlox --help'.", script.name());
+                std::process::exit(64);
+            }
+            self.run_compiled(&bytes);
+        } else {
+            let source = self.decode(bytes);
+            self.run(source, false);
+        }
+    }
 
-        if self.had_error { std::process::exit(65); }
-        if self.had_runtime_error { std::process::exit(70); }
+    /// Reads `script`, exiting if it can't be read.
+    fn load(&mut self, script: &Script) -> Vec<u8> {
+        self.file_name = script.name().to_string();
+        match script.read() {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("rlox: {}: {}", script.name(), e);
+                std::process::exit(66);
+            },
+        }
     }
 
-    fn run(&mut self, source: String) {
-        let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens();
+    /// Reads `script` as text, exiting if it can't be read or isn't UTF-8.
+    fn load_source(&mut self, script: &Script) -> String {
+        let bytes = self.load(script);
+        self.decode(bytes)
+    }
+
+    fn decode(&mut self, bytes: Vec<u8>) -> String {
+        match String::from_utf8(bytes) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("rlox: {}: {}", self.file_name, e);
+                std::process::exit(65);
+            },
+        }
+    }
+
+    /// Scans `source`, reporting any errors.
+    fn scan(&mut self, source: String) -> Option<Vec<Token>> {
+        let offset = self.add_input(&source);
+        match Scanner::with_offset(source, offset).scan_tokens() {
+            Ok(tokens) => Some(tokens.to_owned()),
+            Err(errors) => {
+                self.report_static(&errors);
+                None
+            },
+        }
+    }
+
+    /// Scans, parses and resolves `source`, reporting any errors.
+    fn analyze(&mut self, source: String) -> Option<Vec<Stmt>> {
+        let tokens = self.scan(source)?;
+        let mut statements = match Parser::new(tokens).parse() {
+            Ok(statements) => statements,
+            Err(errors) => {
+                self.report_static(&errors);
+                return None;
+            },
+        };
+
+        if let Err(errors) = Resolver::new().resolve(&mut statements) {
+            self.report_static(&errors);
+            return None;
+        }
+        Some(statements)
+    }
+
+    /// `rlox tokens`: prints each token with its position.
+    fn tokens(&mut self, source: String) {
+        let Some(tokens) = self.scan(source) else { return; };
+        for token in tokens {
+            println!("{}:{} {:?} {}", token.line, token.column, token.token_type, token.lexeme);
+        }
+    }
+
+    /// `rlox ast`: prints the syntax tree to `output`, or stdout.
+    fn ast(&mut self, source: String, format: AstFormat, output: Option<&str>) {
+        let Some(statements) = self.analyze(source) else { return; };
+        let text = match format {
+            AstFormat::Sexpr => sexpr::program(&statements),
+            AstFormat::Debug => format!("{:#?}\n", statements),
+            AstFormat::Json => ast_json::program(&statements).pretty() + "\n",
+        };
+
+        match output {
+            Some(path) => write_output(path, text.as_bytes()),
+            None => print!("{}", text),
+        }
+    }
 
-        if let None = tokens {
-            self.had_error = true;
+    /// `rlox check`: reports static errors without running anything.
+    fn check(&mut self, source: String) {
+        let Some(statements) = self.analyze(source) else { return; };
+        if let Err(errors) = Compiler::new().compile(&statements) {
+            self.report_static(&errors);
+        }
+    }
+
+    /// `rlox fmt`: rewrites a file formatted, or prints the formatted
+    /// source when it came from `-e` or stdin. With `check`, only reports
+    /// whether it would change.
+    fn fmt(&mut self, source: String, script: &Script, check: bool) {
+        self.add_input(&source);
+        let formatted = match formatter::format(&source) {
+            Ok(formatted) => formatted,
+            Err(errors) => return self.report_static(&errors),
+        };
+
+        if check {
+            if formatted != source {
+                eprintln!("{}: would be reformatted", self.file_name);
+                std::process::exit(1);
+            }
             return;
         }
 
-        let tokens = tokens.unwrap().to_owned();
+        match script {
+            Script::File(path) if formatted != source => write_output(path, formatted.as_bytes()),
+            Script::File(_) => {},
+            _ => print!("{}", formatted),
+        }
+    }
+
+    /// 65 after a static error, 70 after a runtime error, otherwise 0.
+    fn exit_code(&self) -> i32 {
+        if self.had_error {
+            65
+        } else if self.had_runtime_error {
+            70
+        } else {
+            0
+        }
+    }
+
+    /// Runs `source` on the selected backend. With `echo`, a lone
+    /// expression statement prints its value, as typed at the REPL.
+    fn run(&mut self, source: String, echo: bool) {
+        let Some(statements) = self.analyze(source) else { return; };
+
+        match (echo, statements.as_slice()) {
+            (true, [Stmt::Expression(expr)]) => self.echo(expr),
+            _ => self.execute(statements),
+        }
+    }
+
+    /// Evaluates a lone expression and prints its value, unless it is nil:
+    /// calling a function that returns nothing or assigning nil shouldn't
+    /// answer back.
+    fn echo(&mut self, expr: &Expr) {
+        let result = match self.backend {
+            Backend::Interpreter => self.interpreter.evaluate(expr),
+            Backend::Vm => {
+                let function = match Compiler::new().compile_expression(expr) {
+                    Ok(function) => function,
+                    Err(errors) => return self.report_static(&errors),
+                };
+                self.vm.evaluate(function)
+            },
+        };
+
+        match result {
+            Ok(Value::Nil) => {},
+            Ok(value) => println!("{}", value),
+            Err(error) => self.report_runtime(&error),
+        }
+    }
+
+    /// `rlox run-ast`: runs a program given as a JSON syntax tree.
+    fn run_ast(&mut self, source: String) {
+        let statements = json::parse(&source).and_then(|json| ast_json::read_program(&json));
+        // Positions in the tree refer to source we don't have.
+        self.add_input("");
+        let mut statements = match statements {
+            Ok(statements) => statements,
+            Err(e) => {
+                eprintln!("rlox: {}: {}", self.file_name, e);
+                self.had_error = true;
+                return;
+            },
+        };
+
+        if let Err(errors) = Resolver::new().resolve(&mut statements) {
+            return self.report_static(&errors);
+        }
+        self.execute(statements);
+    }
 
-        let mut parser = Parser::new(tokens);
-        let expr = parser.parse();
-        if let None = expr {
-            self.had_error = true;
+    /// Runs resolved statements on the selected backend, or compiles them
+    /// to `compile_target`.
+    fn execute(&mut self, expr: Vec<Stmt>) {
+        if let Some(target) = self.compile_target.as_ref() {
+            let function = match Compiler::new().compile(&expr) {
+                Ok(function) => function,
+                Err(errors) => return self.report_static(&errors),
+            };
+            write_output(target, &loxc::serialize(&function));
             return;
         }
 
-        let expr = expr.unwrap();
+        let result = match self.backend {
+            Backend::Interpreter => self.interpreter.interpret(expr),
+            Backend::Vm => {
+                let function = match Compiler::new().compile(&expr) {
+                    Ok(function) => function,
+                    Err(errors) => return self.report_static(&errors),
+                };
+                self.vm.interpret(function)
+            },
+        };
+        if let Err(error) = result {
+            self.report_runtime(&error);
+        }
+    }
 
-        if self.extract_ast && self.target_file.is_some() {
-            let target_file = self.target_file.as_ref().unwrap();
-            let mut file = fs::File::create(target_file).unwrap();
-            file.write_all(format!("{:#?}", expr.clone()).as_bytes()).unwrap();
+    /// Runs a script that was compiled ahead of time with `--compile`.
+    fn run_compiled(&mut self, bytes: &[u8]) {
+        let function = match loxc::deserialize(bytes) {
+            Ok(function) => function,
+            Err(e) => {
+                eprintln!("{}", e);
+                self.had_error = true;
+                return;
+            },
+        };
 
+        // There is no source to show, so diagnostics only give the line.
+        self.add_input("");
+        if let Err(error) = self.vm.interpret(Rc::new(function)) {
+            self.report_runtime(&error);
         }
+    }
 
-        let mut interpreter = Interpreter::new();
-        if let None = interpreter.interpret(expr) {
-            self.had_runtime_error = true;
+    /// Prints errors found before the program started running.
+    fn report_static(&mut self, errors: &[LoxError]) {
+        for error in errors {
+            eprintln!("{}", self.render(error));
         }
+        self.had_error = true;
     }
 
-    pub fn error(line: usize, msg: String) {
-        Self::report(line, "".to_string(), msg);
+    fn report_runtime(&mut self, error: &LoxError) {
+        eprintln!("{}", self.render(error));
+        self.had_runtime_error = true;
     }
 
-    pub fn report(line: usize, where_: String, msg: String) {
-        eprintln!("line[{line}] Error {where_}: {msg}");
+    /// Adds `source` to the session under the current file name, returning
+    /// where it starts.
+    fn add_input(&mut self, source: &str) -> usize {
+        let start = self.source.len();
+        self.source.push_str(source);
+        self.inputs.push((start, self.file_name.clone()));
+        start
+    }
+
+    /// Renders `error` against the input its span points into, or the
+    /// latest input if it has no span.
+    fn render(&self, error: &LoxError) -> String {
+        let latest = self.inputs.len().saturating_sub(1);
+        let index = error.span()
+            .and_then(|span| self.inputs.iter().rposition(|(start, _)| *start <= span.start))
+            .unwrap_or(latest);
+        let Some((start, name)) = self.inputs.get(index) else {
+            return diagnostic::render(error, &self.file_name, "");
+        };
+        let end = self.inputs.get(index + 1).map_or(self.source.len(), |(next, _)| *next);
+        diagnostic::render_at(error, name, &self.source[*start..end], *start)
+    }
+}
+
+/// Writes `bytes` to `path`, exiting if that fails.
+fn write_output(path: &str, bytes: &[u8]) {
+    if let Err(e) = fs::write(path, bytes) {
+        eprintln!("rlox: {}: {}", path, e);
+        std::process::exit(74);
     }
 }
 
+/// The tree-walking interpreter recurses through several Rust frames per
+/// Lox call, more than the usual 8 MiB main stack holds at the call depth
+/// limit, so everything runs on a thread with room to spare.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
 fn main() {
+    let session = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(session)
+        .expect("failed to start the interpreter thread");
+    // A panic has already printed its message; exit the way it would have.
+    std::process::exit(session.join().unwrap_or(101));
+}
+
+/// Parses the command line and runs it, returning the exit code.
+fn session() -> i32 {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let cli = match cli::parse(&args) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("rlox: {}\nTry 'rlox --help'.", e);
+            std::process::exit(64);
+        },
+    };
+
     let mut lox = Lox::new();
+    lox.backend = cli.backend;
+    lox.compile_target = cli.compile;
+    lox.set_args(cli.args);
 
-    let args = std::env::args().collect::<Vec<String>>();
-
-    if args.len() > 4 {
-        eprintln!("Usage: lox [script]");
-        std::process::exit(64);
-    } else if args.len() == 4 {
-        // Weak arguments check.
-        // TODO: check for the `--extract-ast` flag.
-        lox.extract_ast = true;
-        lox.target_file = Some(args[3].clone());
-        lox.run_file(args[1].clone());
-    }else if args.len() == 2 {
-        lox.run_file(args[1].clone());
-    } else {
-        lox.run_prompt();
+    match cli.command {
+        Command::Help => println!("{}", cli::USAGE),
+        Command::Version => println!("rlox {}", env!("CARGO_PKG_VERSION")),
+        Command::Repl => lox.run_prompt(),
+        Command::Run(script) => lox.run_file(&script),
+        Command::RunAst(script) => {
+            let source = lox.load_source(&script);
+            lox.run_ast(source);
+        },
+        Command::Tokens(script) => {
+            let source = lox.load_source(&script);
+            lox.tokens(source);
+        },
+        Command::Ast { script, format, output } => {
+            let source = lox.load_source(&script);
+            lox.ast(source, format, output.as_deref());
+        },
+        Command::Check(script) => {
+            let source = lox.load_source(&script);
+            lox.check(source);
+        },
+        Command::Fmt { script, check } => {
+            let source = lox.load_source(&script);
+            lox.fmt(source, &script, check);
+        },
     }
+    lox.exit_code()
 }
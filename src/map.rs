@@ -0,0 +1,89 @@
+use std::{collections::HashMap, fmt};
+
+use crate::value::Value;
+
+/// A map key. Only strings and numbers can be keys, and they compare by
+/// value, so `m[1]` and `m[1.0]` are the same entry.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    String(String),
+    /// The number's bits, with `-0` stored as `0` so the two are one key.
+    Number(u64),
+}
+
+impl MapKey {
+    pub fn new(value: &Value) -> Result<MapKey, String> {
+        match value {
+            Value::String(s) => Ok(MapKey::String(s.clone())),
+            Value::Number(n) if n.is_nan() => Err("A map key can't be NaN.".to_string()),
+            Value::Number(n) => Ok(MapKey::Number((n + 0.0).to_bits())),
+            _ => Err("Map keys must be strings or numbers.".to_string()),
+        }
+    }
+
+    pub fn value(&self) -> Value {
+        match self {
+            MapKey::String(s) => Value::String(s.clone()),
+            MapKey::Number(bits) => Value::Number(f64::from_bits(*bits)),
+        }
+    }
+}
+
+impl fmt::Display for MapKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapKey::String(s) => write!(f, "{:?}", s),
+            MapKey::Number(bits) => write!(f, "{}", f64::from_bits(*bits)),
+        }
+    }
+}
+
+/// The entries of a map, kept in the order their keys were first added.
+#[derive(Debug, Default)]
+pub struct LoxMap {
+    entries: Vec<(MapKey, Value)>,
+    /// Where each key's entry is in `entries`.
+    positions: HashMap<MapKey, usize>,
+}
+
+impl LoxMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, key: &MapKey) -> Option<&Value> {
+        self.positions.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    pub fn contains(&self, key: &MapKey) -> bool {
+        self.positions.contains_key(key)
+    }
+
+    /// Sets `key` to `value`. A key that is already there keeps its place.
+    pub fn insert(&mut self, key: MapKey, value: Value) {
+        match self.positions.get(&key) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            },
+        }
+    }
+
+    pub fn remove(&mut self, key: &MapKey) -> Option<Value> {
+        let i = self.positions.remove(key)?;
+        let (_, value) = self.entries.remove(i);
+        for (key, _) in &self.entries[i..] {
+            *self.positions.get_mut(key).unwrap() -= 1;
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(MapKey, Value)> {
+        self.entries.iter()
+    }
+}
@@ -0,0 +1,142 @@
+use std::{io::{self, Write}, time::{SystemTime, UNIX_EPOCH}};
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    callable::NativeFunction,
+    map::{LoxMap, MapKey},
+    value::{ListItems, Value},
+};
+
+/// Every native function defined as a global when an interpreter starts.
+pub fn all() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction { name: "clock", arity: 0, function: clock },
+        NativeFunction { name: "env", arity: 1, function: env },
+        NativeFunction { name: "exit", arity: 1, function: exit },
+        NativeFunction { name: "len", arity: 1, function: len },
+        NativeFunction { name: "push", arity: 2, function: push },
+        NativeFunction { name: "pop", arity: 1, function: pop },
+        NativeFunction { name: "insert", arity: 3, function: insert },
+        NativeFunction { name: "remove", arity: 2, function: remove },
+        NativeFunction { name: "keys", arity: 1, function: keys },
+        NativeFunction { name: "values", arity: 1, function: values },
+        NativeFunction { name: "has", arity: 2, function: has },
+        NativeFunction { name: "delete", arity: 2, function: delete },
+    ]
+}
+
+fn clock(_args: &[Value]) -> Result<Value, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?;
+
+    Ok(Value::Number(now.as_secs_f64()))
+}
+
+/// `env(name)`: the environment variable `name`, or nil when it isn't set.
+fn env(args: &[Value]) -> Result<Value, String> {
+    let Value::String(name) = &args[0] else {
+        return Err("Variable name must be a string.".to_string());
+    };
+
+    Ok(std::env::var(name).map_or(Value::Nil, Value::String))
+}
+
+/// `exit(code)`: ends the program with `code` as its exit status.
+fn exit(args: &[Value]) -> Result<Value, String> {
+    match args[0] {
+        Value::Number(code) if code.fract() == 0.0 && (0.0..=255.0).contains(&code) => {
+            io::stdout().flush().map_err(|e| e.to_string())?;
+            std::process::exit(code as i32)
+        },
+        _ => Err("Exit code must be an integer between 0 and 255.".to_string()),
+    }
+}
+
+/// `len(value)`: how many items a list has, entries a map has, or
+/// characters a string has.
+fn len(args: &[Value]) -> Result<Value, String> {
+    match &args[0] {
+        Value::List(items) => Ok(Value::Number(items.borrow().len() as f64)),
+        Value::Map(map) => Ok(Value::Number(map.borrow().len() as f64)),
+        Value::String(s) => Ok(Value::Number(s.chars().count() as f64)),
+        _ => Err("Can only take the length of a list, a map or a string.".to_string()),
+    }
+}
+
+/// `push(list, value)`: adds `value` to the end of `list`.
+fn push(args: &[Value]) -> Result<Value, String> {
+    list_argument(&args[0], "push")?.borrow_mut().push(args[1].clone());
+    Ok(Value::Nil)
+}
+
+/// `pop(list)`: removes the last item of `list` and returns it.
+fn pop(args: &[Value]) -> Result<Value, String> {
+    list_argument(&args[0], "pop")?
+        .borrow_mut()
+        .pop()
+        .ok_or_else(|| "Can't pop from an empty list.".to_string())
+}
+
+/// `insert(list, index, value)`: puts `value` at `index`, moving the items
+/// from there on up one. `index` may be the length, to add at the end.
+fn insert(args: &[Value]) -> Result<Value, String> {
+    let items = list_argument(&args[0], "insert")?;
+    let len = items.borrow().len();
+    let index = match &args[1] {
+        Value::Number(n) if *n == len as f64 => len,
+        index => Value::list_index(index, len)?,
+    };
+    items.borrow_mut().insert(index, args[2].clone());
+    Ok(Value::Nil)
+}
+
+/// `remove(list, index)`: takes out the item at `index` and returns it.
+fn remove(args: &[Value]) -> Result<Value, String> {
+    let items = list_argument(&args[0], "remove")?;
+    let len = items.borrow().len();
+    let index = Value::list_index(&args[1], len)?;
+    let item = items.borrow_mut().remove(index);
+    Ok(item)
+}
+
+/// `keys(map)`: a new list of the map's keys, in the order they were added.
+fn keys(args: &[Value]) -> Result<Value, String> {
+    let map = map_argument(&args[0], "keys")?.borrow();
+    Ok(Value::list(map.iter().map(|(key, _)| key.value()).collect()))
+}
+
+/// `values(map)`: a new list of the map's values, in the same order as
+/// `keys`.
+fn values(args: &[Value]) -> Result<Value, String> {
+    let map = map_argument(&args[0], "values")?.borrow();
+    Ok(Value::list(map.iter().map(|(_, value)| value.clone()).collect()))
+}
+
+/// `has(map, key)`: whether `key` is in the map.
+fn has(args: &[Value]) -> Result<Value, String> {
+    let key = MapKey::new(&args[1])?;
+    Ok(Value::Bool(map_argument(&args[0], "has")?.borrow().contains(&key)))
+}
+
+/// `delete(map, key)`: takes `key` out of the map, returning whether it was
+/// there.
+fn delete(args: &[Value]) -> Result<Value, String> {
+    let key = MapKey::new(&args[1])?;
+    Ok(Value::Bool(map_argument(&args[0], "delete")?.borrow_mut().remove(&key).is_some()))
+}
+
+fn list_argument<'a>(value: &'a Value, native: &str) -> Result<&'a ListItems, String> {
+    match value {
+        Value::List(items) => Ok(items),
+        _ => Err(format!("The first argument to '{}' must be a list.", native)),
+    }
+}
+
+fn map_argument<'a>(value: &'a Value, native: &str) -> Result<&'a Rc<RefCell<LoxMap>>, String> {
+    match value {
+        Value::Map(map) => Ok(map),
+        _ => Err(format!("The first argument to '{}' must be a map.", native)),
+    }
+}
@@ -1,49 +1,151 @@
 use std::fmt;
 
-use crate::{token::{ Token, TokenType }, Lox};
+use crate::{error::LoxError, token::{ Span, Token, TokenType }, value::Value};
+
+/// How deeply statements and expressions may nest. Every later stage walks
+/// the tree recursively, so a deeper program would overflow the stack.
+pub const MAX_NESTING: usize = 256;
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    errors: Vec<LoxError>,
+    /// How many loops enclose the current statement within its function,
+    /// so `break` and `continue` outside one can be reported.
+    loop_depth: usize,
+    /// How many blocks, statement bodies and subexpressions enclose the
+    /// current token.
+    nesting: usize,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expr {
     Binary(Box<Expr>, Token, Box<Expr>),
-    Grouping(Box<Expr>),
-    Literal(String),
+    /// The span covers the parentheses.
+    Grouping(Box<Expr>, Span),
+    Literal(Value, Span),
     Unary(Token, Box<Expr>),
-    Variable(Token),
+    /// The depth is filled in by the resolver: how many scopes out the
+    /// variable lives, or `None` when it is global.
+    Variable(Token, Option<usize>),
     Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
-    Assign(Token, Box<Expr>),
+    Assign(Token, Box<Expr>, Option<usize>),
     Logical(Box<Expr>, Token, Box<Expr>),
+    Call(Box<Expr>, Token, Vec<Expr>),
+    Get(Box<Expr>, Token),
+    Set(Box<Expr>, Token, Box<Expr>),
+    This(Token, Option<usize>),
+    /// `super.method`: the `super` keyword, the method name and its depth.
+    Super(Token, Token, Option<usize>),
+    /// `[a, b]`. The span covers the brackets.
+    List(Vec<Expr>, Span),
+    /// `{key: value}`: each key, the `:` after it and its value. The span
+    /// covers the braces.
+    Map(Vec<(Expr, Token, Expr)>, Span),
+    /// `object[index]`, keeping the `]` for errors like `Call` keeps its `)`.
+    Index(Box<Expr>, Token, Box<Expr>),
+    /// `object[index] = value`.
+    SetIndex(Box<Expr>, Token, Box<Expr>, Box<Expr>),
 }
 
+/// Prints the expression as Lox source. Parentheses come only from
+/// `Grouping` nodes, so a parsed expression prints the way it was written.
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Expr::Binary(left, op, right) => {
-                write!(f, "({} {} {})", *left, op.lexeme, *right)
-            },
-            Expr::Grouping(expression) => {
-                write!(f, "(group {})", *expression)
+            Expr::Binary(left, op, right) | Expr::Logical(left, op, right) => {
+                write!(f, "{} {} {}", left, op.lexeme, right)
             },
-            Expr::Literal(lit) => {
-                write!(f, "{}", lit)
+            Expr::Grouping(expression, _) => write!(f, "({})", expression),
+            Expr::Literal(Value::String(s), _) => {
+                // Lox strings have no escapes, so pick a quote they don't contain.
+                let quote = ['"', '\'', '`'].into_iter().find(|&quote| !s.contains(quote)).unwrap_or('"');
+                write!(f, "{quote}{s}{quote}")
             },
-            Expr::Unary(op, right) => {
-                write!(f, "({} {})", op.lexeme, *right)
+            Expr::Literal(lit, _) => write!(f, "{}", lit),
+            Expr::Unary(op, right) => write!(f, "{}{}", op.lexeme, right),
+            Expr::Ternary(cond, left, right) => write!(f, "{} ? {} : {}", cond, left, right),
+            Expr::Variable(name, _) => write!(f, "{}", name.lexeme),
+            Expr::Assign(name, value, _) => write!(f, "{} = {}", name.lexeme, value),
+            Expr::Call(callee, _, arguments) => {
+                write!(f, "{}(", callee)?;
+                for (i, argument) in arguments.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", argument)?;
+                }
+                write!(f, ")")
             },
-            Expr::Ternary(cond, left, right) => {
-                write!(f, "({} ? {} : {})", *cond, *left, *right)
+            Expr::Get(object, name) => write!(f, "{}.{}", object, name.lexeme),
+            Expr::Set(object, name, value) => write!(f, "{}.{} = {}", object, name.lexeme, value),
+            Expr::This(_, _) => write!(f, "this"),
+            Expr::Super(_, method, _) => write!(f, "super.{}", method.lexeme),
+            Expr::List(items, _) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
             },
-            Expr::Variable(var) => {
-                write!(f, "{}", var.lexeme)
+            Expr::Map(entries, _) => {
+                write!(f, "{{")?;
+                for (i, (key, _, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                write!(f, "}}")
             },
-            Expr::Assign(name, expr) => {
-                write!(f, "({} = {})", name.lexeme, *expr)
+            Expr::Index(object, _, index) => write!(f, "{}[{}]", object, index),
+            Expr::SetIndex(object, _, index, value) => write!(f, "{}[{}] = {}", object, index, value),
+        }
+    }
+}
+
+impl Expr {
+    /// How many levels the tree under this expression has, itself included.
+    fn height(&self) -> usize {
+        let children = match self {
+            Expr::Literal(..) | Expr::Variable(..) | Expr::This(..) | Expr::Super(..) => 0,
+            Expr::Grouping(inner, _) | Expr::Unary(_, inner) | Expr::Assign(_, inner, _) | Expr::Get(inner, _) => {
+                inner.height()
             },
-            _ => todo!()
+            Expr::Binary(left, _, right)
+            | Expr::Logical(left, _, right)
+            | Expr::Set(left, _, right)
+            | Expr::Index(left, _, right) => left.height().max(right.height()),
+            Expr::Ternary(a, b, c) | Expr::SetIndex(a, _, b, c) => a.height().max(b.height()).max(c.height()),
+            Expr::Call(callee, _, arguments) => arguments.iter().map(Expr::height).fold(callee.height(), usize::max),
+            Expr::List(items, _) => items.iter().map(Expr::height).max().unwrap_or(0),
+            Expr::Map(entries, _) => entries.iter().map(|(key, _, value)| key.height().max(value.height())).max().unwrap_or(0),
+        };
+        children + 1
+    }
+
+    /// Where in the source this expression was written.
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Binary(left, _, right)
+            | Expr::Logical(left, _, right)
+            | Expr::Ternary(left, _, right) => left.span().to(right.span()),
+            Expr::Grouping(_, span)
+            | Expr::Literal(_, span)
+            | Expr::List(_, span)
+            | Expr::Map(_, span) => *span,
+            Expr::Unary(op, right) => op.span.to(right.span()),
+            Expr::Variable(name, _) | Expr::This(name, _) => name.span,
+            Expr::Assign(name, value, _) => name.span.to(value.span()),
+            Expr::Call(callee, paren, _) => callee.span().to(paren.span),
+            Expr::Get(object, name) => object.span().to(name.span),
+            Expr::Set(object, _, value) => object.span().to(value.span()),
+            Expr::Super(keyword, method, _) => keyword.span.to(method.span),
+            Expr::Index(object, bracket, _) => object.span().to(bracket.span),
+            Expr::SetIndex(object, _, _, value) => object.span().to(value.span()),
         }
     }
 }
@@ -51,119 +153,257 @@ impl fmt::Display for Expr {
 #[derive(Debug, PartialEq, Clone)]
 pub enum Stmt {
     Expression(Box<Expr>),
-    Print(Box<Expr>),
+    /// Statements that don't keep their keyword carry their whole span.
+    Print(Box<Expr>, Span),
     Let(Token, Option<Box<Expr>>),
-    Block(Vec<Box<Stmt>>),
-    If(Expr, Box<Stmt>, Box<Option<Stmt>>),
-    While(Expr, Box<Stmt>),
+    Block(Vec<Stmt>, Span),
+    If(Expr, Box<Stmt>, Box<Option<Stmt>>, Span),
+    /// The increment of a `for` loop runs after the body, `continue` or not.
+    While(Expr, Box<Stmt>, Option<Box<Expr>>, Span),
+    Function(FunctionDecl),
+    Return(Token, Option<Box<Expr>>),
+    Class(Token, Option<Expr>, Vec<FunctionDecl>),
+    Break(Token),
+    Continue(Token),
 }
 
-impl fmt::Display for Stmt {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl Stmt {
+    /// Where in the source this statement was written. Declarations only
+    /// cover their name and, for variables, the initializer.
+    pub fn span(&self) -> Span {
         match self {
-            Stmt::Expression(expr) => {
-                write!(f, "{}", *expr)
+            Stmt::Expression(expr) => expr.span(),
+            Stmt::Print(_, span)
+            | Stmt::Block(_, span)
+            | Stmt::If(_, _, _, span)
+            | Stmt::While(_, _, _, span) => *span,
+            Stmt::Let(name, initializer) => match initializer {
+                Some(initializer) => name.span.to(initializer.span()),
+                None => name.span,
             },
-            Stmt::Print(expr) => {
-                write!(f, "print({})", *expr)
+            Stmt::Function(declaration) => declaration.name.span,
+            Stmt::Return(keyword, value) => match value {
+                Some(value) => keyword.span.to(value.span()),
+                None => keyword.span,
             },
-            Stmt::Let(name, expr) => {
-                if let None = expr {
-                    write!(f, "var {};", name.lexeme)
-                } else {
-                    write!(f, "var {} = {};", name.lexeme, (expr.as_ref()).unwrap())
+            Stmt::Class(name, _, _) => name.span,
+            Stmt::Break(keyword) | Stmt::Continue(keyword) => keyword.span,
+        }
+    }
+}
+
+/// A named function or method, shared by `fun` declarations and class bodies.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FunctionDecl {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Stmt>,
+}
+
+/// Prints the statement as Lox source, indenting nested blocks by four
+/// spaces. A `for` loop prints as the `while` loop it was parsed into,
+/// inside a block when it declared a variable, and keeps its increment.
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stmt::Expression(expr) => write!(f, "{};", expr),
+            Stmt::Print(expr, _) => write!(f, "print {};", expr),
+            Stmt::Let(name, None) => write!(f, "var {};", name.lexeme),
+            Stmt::Let(name, Some(initializer)) => write!(f, "var {} = {};", name.lexeme, initializer),
+            Stmt::Block(statements, _) => write_block(f, statements),
+            Stmt::If(cond, then, else_, _) => {
+                write!(f, "if ({}) {}", cond, then)?;
+                if let Some(else_) = else_.as_ref() {
+                    write!(f, " else {}", else_)?;
                 }
+                Ok(())
             },
-            Stmt::Block(stmts) => {
-                write!(f, "{{\n").unwrap();
-                for stmt in stmts {
-                    write!(f,"\t{}\n", *stmt).unwrap();
+            Stmt::While(cond, body, None, _) => write!(f, "while ({}) {}", cond, body),
+            Stmt::While(cond, body, Some(increment), _) => write!(f, "for (; {}; {}) {}", cond, increment, body),
+            Stmt::Function(declaration) => write!(f, "fun {}", declaration),
+            Stmt::Return(_, None) => write!(f, "return;"),
+            Stmt::Return(_, Some(value)) => write!(f, "return {};", value),
+            Stmt::Class(name, superclass, methods) => {
+                write!(f, "class {} ", name.lexeme)?;
+                if let Some(superclass) = superclass {
+                    write!(f, "< {} ", superclass)?;
+                }
+                if methods.is_empty() {
+                    return write!(f, "{{}}");
                 }
 
-                write!(f, "}}")
-            },
-            Stmt::If(cond, consequence, alternative) => {
-                write!(f, "if ({}) {{\n\t", cond).unwrap();
-                write!(f, "{}\n}}", consequence).unwrap();
-                if (*alternative).is_some() {
-                write!(f, " else {{\n\t{}", alternative.as_ref().as_ref().unwrap()).unwrap();
+                writeln!(f, "{{")?;
+                for method in methods {
+                    writeln!(f, "{}", indent(&method.to_string()))?;
                 }
-                write!(f, "\n}}")
+                write!(f, "}}")
             },
-            _ => todo!()
+            Stmt::Break(_) => write!(f, "break;"),
+            Stmt::Continue(_) => write!(f, "continue;"),
         }
     }
 }
 
+/// `name(params) { body }`, as in a declaration without the `fun`.
+impl fmt::Display for FunctionDecl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<&str> = self.params.iter().map(|param| param.lexeme.as_str()).collect();
+        write!(f, "{}({}) ", self.name.lexeme, params.join(", "))?;
+        write_block(f, &self.body)
+    }
+}
+
+fn write_block(f: &mut fmt::Formatter<'_>, statements: &[Stmt]) -> fmt::Result {
+    if statements.is_empty() {
+        return write!(f, "{{}}");
+    }
+
+    writeln!(f, "{{")?;
+    for stmt in statements {
+        writeln!(f, "{}", indent(&stmt.to_string()))?;
+    }
+    write!(f, "}}")
+}
+
+/// `text` with every line indented one level. Lines inside a multi-line
+/// string literal are left alone so its value doesn't change.
+fn indent(text: &str) -> String {
+    let mut out = String::new();
+    let mut in_string: Option<char> = None;
+    let mut line_start = true;
+    for ch in text.chars() {
+        if line_start && in_string.is_none() && ch != '\n' {
+            out.push_str("    ");
+        }
+        line_start = ch == '\n';
+        out.push(ch);
+
+        match (in_string, ch) {
+            (None, '"' | '\'') => in_string = Some(ch),
+            (Some(quote), ch) if ch == quote => in_string = None,
+            _ => {},
+        }
+    }
+    out
+}
+
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
+        Parser { tokens, current: 0, errors: Vec::new(), loop_depth: 0, nesting: 0 }
     }
 
-    pub fn parse(&mut self) -> Option<Vec<Stmt>> {
+    /// Parses every declaration, synchronizing after a syntax error so the
+    /// rest of the program is still checked.
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<LoxError>> {
         let mut statements: Vec<Stmt> = Vec::new();
         while !self.is_at_end() {
-            let stmt = self.declaration();
-            if let None = stmt {
-                return None;
+            if let Some(stmt) = self.declaration() {
+                statements.push(stmt);
             }
-            statements.push(stmt.unwrap());
         }
-        return Some(statements);
-    }
 
-    fn declaration(&mut self) -> Option<Stmt> {
-        // if self.match_tokens(&[TokenType::Fun]) {
-        //     return self.function("function");
-        // }
-        if self.match_tokens(&[TokenType::Var]) {
-            return self.var_declaration();
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors));
         }
+        Ok(statements)
+    }
 
-        let stmt = self.statement();
-        if let None = stmt {
+    fn declaration(&mut self) -> Option<Stmt> {
+        let stmt = if self.match_tokens(&[TokenType::Class]) {
+            self.class_declaration()
+        } else if self.match_tokens(&[TokenType::Fun]) {
+            self.function("function").map(Stmt::Function)
+        } else if self.match_tokens(&[TokenType::Var]) {
+            self.var_declaration()
+        } else {
+            self.statement()
+        };
+        if stmt.is_none() {
             self.synchronize();
             return None;
         }
-        return Some(stmt.unwrap());
+        Some(stmt.unwrap())
     }
 
-    fn block(&mut self) -> Option<Vec<Box<Stmt>>> {
-        let mut statements: Vec<Box<Stmt>> = Vec::new();
+    fn class_declaration(&mut self) -> Option<Stmt> {
+        let name = self.consume(TokenType::Identifier, "Expect class name.".to_string())?;
+
+        let mut superclass = None;
+        if self.match_tokens(&[TokenType::Less]) {
+            let superclass_name = self.consume(TokenType::Identifier, "Expect superclass name.".to_string())?;
+            superclass = Some(Expr::Variable(superclass_name, None));
+        }
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.".to_string())?;
+
+        let mut methods: Vec<FunctionDecl> = Vec::new();
         while !self.check(TokenType::RightBrace) && !self.is_at_end() {
-            let declaration = self.declaration();
-            if let None = declaration {
-                return None;
+            methods.push(self.function("method")?);
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.".to_string())?;
+
+        Some(Stmt::Class(name, superclass, methods))
+    }
+
+    fn function(&mut self, kind: &str) -> Option<FunctionDecl> {
+        let name = self.consume(TokenType::Identifier, format!("Expect {kind} name."))?;
+        self.consume(TokenType::LeftParen, format!("Expect '(' after {kind} name."))?;
+
+        let mut params: Vec<Token> = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if params.len() >= 255 {
+                    self.error(self.peek(), "Can't have more than 255 parameters.".to_string());
+                }
+
+                let param = self.consume(TokenType::Identifier, "Expect parameter name.".to_string())?;
+                params.push(param);
+
+                if !self.match_tokens(&[TokenType::Comma]) {
+                    break;
+                }
             }
-            statements.push(Box::new(declaration.unwrap()));
         }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.".to_string())?;
 
-        self.consume(TokenType::RightBrace, "Expect '}' after block.".to_string());
+        self.consume(TokenType::LeftBrace, format!("Expect '{{' before {kind} body."))?;
+        // A loop around the declaration doesn't reach into the body.
+        let enclosing_loops = std::mem::replace(&mut self.loop_depth, 0);
+        let body = self.block();
+        self.loop_depth = enclosing_loops;
+        let body = body?;
 
-        return Some(statements);
+        Some(FunctionDecl { name, params, body })
+    }
+
+    fn block(&mut self) -> Option<Vec<Stmt>> {
+        self.nested(|parser| {
+            let mut statements: Vec<Stmt> = Vec::new();
+            while !parser.check(TokenType::RightBrace) && !parser.is_at_end() {
+                statements.push(parser.declaration()?);
+            }
+
+            parser.consume(TokenType::RightBrace, "Expect '}' after block.".to_string());
+
+            Some(statements)
+        })
     }
 
     fn var_declaration(&mut self) -> Option<Stmt> {
-        let ident = self.consume(TokenType::Identifier, "Expect identifier after 'var'".to_string());
-        if let None = ident {
-            return None;
-        }
-        let ident = ident.unwrap();
+        let ident = self.consume(TokenType::Identifier, "Expect identifier after 'var'".to_string())?;
 
         let mut initializer: Option<Box<Expr>> = None;
         if self.match_tokens(&[TokenType::Equal]) {
-            let expr = self.expression();
-            if let None = expr {
-                return None;
-            }
-            let expr = expr.unwrap();
+            let expr = self.expression()?;
             initializer = Some(Box::new(expr));
         }
 
-        while self.peek().token_type == TokenType::Semicolon {
+        // Only one: in a `for` initializer, the next `;` ends the condition.
+        if self.peek().token_type == TokenType::Semicolon {
             self.advance();
         }
-        return Some(Stmt::Let(ident, initializer));
+        Some(Stmt::Let(ident, initializer))
     }
 
     fn statement(&mut self) -> Option<Stmt> {
@@ -171,13 +411,19 @@ impl Parser {
             return self.print_statement();
         }
 
+        if self.match_tokens(&[TokenType::Return]) {
+            return self.return_statement();
+        }
+
+        if self.match_tokens(&[TokenType::Break, TokenType::Continue]) {
+            return self.loop_jump_statement();
+        }
+
         if self.match_tokens(&[TokenType::LeftBrace]) {
-            let block = self.block();
-            if let None = block {
-                return None;
-            }
+            let start = self.previous().span;
+            let block = self.block()?;
 
-            return Some(Stmt::Block(block.unwrap()));
+            return Some(Stmt::Block(block, self.span_from(start)));
         }
 
         if self.match_tokens(&[TokenType::If]) {
@@ -192,15 +438,14 @@ impl Parser {
             return self.for_statement();
         }
 
-        return self.expression_statement();
+        self.expression_statement()
     }
 
     fn for_statement(&mut self) -> Option<Stmt> {
-        if let None = self.consume(
+        let keyword = self.previous().span;
+        self.consume(
             TokenType::LeftParen, "Expect '(' after 'for'.".to_string()
-        ) {
-            return None;
-        }
+        )?;
 
         #[allow(unused_assignments)]
         let mut initializer: Option<Stmt> = None;
@@ -217,98 +462,107 @@ impl Parser {
             condition = self.expression();
         }
 
-        if let None = self.consume(
+        self.consume(
             TokenType::Semicolon, "Expect ';' after condition.".to_string()
-        ) {
-            return None;
-        }
+        )?;
 
         let mut increment: Option<Expr> = None;
         if !self.check(TokenType::RightParen) {
             increment = self.expression();
         }
 
-        if let None = self.consume(
+        self.consume(
             TokenType::RightParen, "Expect ')' after for clauses.".to_string()
-        ) {
-            return None;
-        }
-        let body_outer = self.statement();
-        if let None = body_outer {
-            return None;
-        }
-        let mut body_inner = body_outer.unwrap();
-        if let Some(inc) = increment {
-            body_inner = Stmt::Block(
-                vec![
-                Box::new(body_inner),
-                Box::new(Stmt::Expression(Box::new(inc)))
-                ]
-            );
-        }
+        )?;
+        let body_inner = self.loop_body()?;
+        let span = self.span_from(keyword);
 
-        let cond: Expr = condition.unwrap_or(Expr::Literal("true".to_string()));
-        body_inner = Stmt::While(cond, Box::new(body_inner));
+        let cond: Expr = condition.unwrap_or(Expr::Literal(Value::Bool(true), keyword));
+        let mut body_inner = Stmt::While(cond, Box::new(body_inner), increment.map(Box::new), span);
 
         if let Some(init_expr) = initializer {
-            body_inner = Stmt::Block(vec![Box::new(init_expr), Box::new(body_inner)]);
+            body_inner = Stmt::Block(vec![init_expr, body_inner], span);
         }
 
-        return Some(body_inner);
+        Some(body_inner)
     }
 
     fn while_statement(&mut self) -> Option<Stmt> {
+        let keyword = self.previous().span;
         self.consume(TokenType::LeftParen, "Expect '(' after 'while'".to_string());
-        let condition = self.expression();
-        if let None = condition { return None; }
-        let condition = condition.unwrap();
+        let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after condition.".to_string());
 
-        let body = self.statement();
-        if let None = body { return None; }
-        let body = Box::new(body.unwrap());
+        let body = Box::new(self.loop_body()?);
 
-        return Some(Stmt::While(condition, body));
+        Some(Stmt::While(condition, body, None, self.span_from(keyword)))
+    }
+
+    fn loop_body(&mut self) -> Option<Stmt> {
+        self.loop_depth += 1;
+        let body = self.nested(Self::statement);
+        self.loop_depth -= 1;
+        body
     }
 
     fn if_statement(&mut self) -> Option<Stmt> {
+        let keyword = self.previous().span;
         self.consume(TokenType::LeftParen, "Expect '(' after 'if'.".to_string());
-        let condition = self.expression();
-        if let None = condition { return None; }
-        let condition = condition.unwrap();
+        let condition = self.expression()?;
 
         self.consume(TokenType::RightParen, "Expect ')' after if condition.".to_string());
         // self.consume(TokenType::LeftBrace, "Expect '{' after if.".to_string());
-        let then_branch = self.statement();
-        if let None = then_branch {
-            return None;
-        }
-        let then_branch = then_branch.unwrap();
+        let then_branch = self.nested(Self::statement)?;
         let mut else_branch = None;
         if self.match_tokens(&[TokenType::Else]) {
-            else_branch = self.statement();
-            if let None = else_branch {
-                return None;
-            }
+            else_branch = Some(self.nested(Self::statement)?);
         }
         Some(Stmt::If(
             condition,
             Box::new(then_branch),
-            Box::new(else_branch)
+            Box::new(else_branch),
+            self.span_from(keyword)
         ))
     }
 
     fn print_statement(&mut self) -> Option<Stmt> {
+        let keyword = self.previous().span;
         let value = self.expression();
         while self.peek().token_type == TokenType::Semicolon {
             self.advance();
         }
 
-        if let None = value {
-            return None;
+        Some(Stmt::Print(Box::new(value?), self.span_from(keyword)))
+    }
+
+    fn return_statement(&mut self) -> Option<Stmt> {
+        let keyword = self.previous();
+        let mut value = None;
+        if !self.check(TokenType::Semicolon) && !self.check(TokenType::RightBrace) {
+            value = Some(Box::new(self.expression()?));
+        }
+
+        while self.peek().token_type == TokenType::Semicolon {
+            self.advance();
         }
 
-        return Some(Stmt::Print(Box::new(value.unwrap())));
+        Some(Stmt::Return(keyword, value))
+    }
+
+    fn loop_jump_statement(&mut self) -> Option<Stmt> {
+        let keyword = self.previous();
+        if self.loop_depth == 0 {
+            self.error(keyword.clone(), format!("Can't use '{}' outside of a loop.", keyword.lexeme));
+        }
+
+        while self.peek().token_type == TokenType::Semicolon {
+            self.advance();
+        }
+
+        if keyword.token_type == TokenType::Break {
+            return Some(Stmt::Break(keyword));
+        }
+        Some(Stmt::Continue(keyword))
     }
 
     fn expression_statement(&mut self) -> Option<Stmt> {
@@ -317,124 +571,96 @@ impl Parser {
             self.advance();
         }
 
-        if let None = value {
-            return None;
-        }
-
-        return Some(Stmt::Expression(Box::new(value.unwrap())));
+        Some(Stmt::Expression(Box::new(value?)))
     }
 
     fn expression(&mut self) -> Option<Expr> {
-        self.assignment()
+        self.nested(Self::assignment)
     }
 
     fn assignment(&mut self) -> Option<Expr> {
-        let expr = self.or();
-        if let None = expr {
-            return None;
-        }
+        let expr = self.or()?;
 
         if self.match_tokens(&[TokenType::Equal]) {
             let equals = self.previous();
-            let value = self.assignment();
-            if let None = value {
-                return None;
+            let value = self.nested(Self::assignment)?;
+
+            match expr {
+                Expr::Variable(var, _) => {
+                    return Some(Expr::Assign(var, Box::new(value), None));
+                },
+                Expr::Get(object, name) => {
+                    return Some(Expr::Set(object, name, Box::new(value)));
+                },
+                Expr::Index(object, bracket, index) => {
+                    return Some(Expr::SetIndex(object, bracket, index, Box::new(value)));
+                },
+                _ => {}
             }
-            let value = value.unwrap();
 
-            if let Some(Expr::Variable(var)) = expr {
-                return Some(Expr::Assign(var, Box::new(value)));
-            }
-
-            Lox::report(equals.line, format!("at '{}'", equals.lexeme), "Invalid assignment Target.".to_string());
+            self.error(equals, "Invalid assignment Target.".to_string());
             return None;
         }
 
-        return expr;
+        Some(expr)
     }
 
     fn or(&mut self) -> Option<Expr> {
-        let expr = self.and();
-        if let None = expr {
-            return None;
-        }
-
-        let mut expr = expr.unwrap();
+        let mut expr = self.and()?;
+        let mut height = expr.height();
         while self.match_tokens(&[TokenType::Or]) {
             let operator = self.previous();
             let right = self.and();
-            if let None = right { break; }
+            if right.is_none() { break; }
             let right = right.unwrap();
+            height = self.chained(height, right.height())?;
 
             expr = Expr::Logical(Box::new(expr), operator, Box::new(right));
         }
 
-        return Some(expr);
+        Some(expr)
     }
 
     fn and(&mut self) -> Option<Expr> {
-        let expr = self.ternary();
-        if let None = expr {
-            return None;
-        }
-        let mut expr = expr.unwrap();
+        let mut expr = self.ternary()?;
+        let mut height = expr.height();
 
         while self.match_tokens(&[TokenType::And]) {
             let op = self.previous();
             let right = self.equality();
-            if let None = right { break; }
+            if right.is_none() { break; }
             let right = right.unwrap();
+            height = self.chained(height, right.height())?;
 
             expr = Expr::Logical(Box::new(expr), op, Box::new(right));
         }
 
-        return Some(expr);
+        Some(expr)
     }
 
     fn ternary(&mut self) -> Option<Expr> {
-        let condition = self.equality();
-        if let None = condition {
-            return None;
-        }
+        let condition = self.equality()?;
 
-        if let false = self.match_tokens(&[TokenType::Qmark]) {
-            return condition;
+        if !self.match_tokens(&[TokenType::Qmark]) {
+            return Some(condition);
         }
 
-        let left = self.primary();
-        if let None = left {
-            Lox::error(self.tokens[self.current].line, "Expect a expression after ?".to_string());
-            return None;
-        }
-        let left = left.unwrap();
+        // A branch that fails to parse has already reported why.
+        let left = self.primary()?;
 
-        if let false = self.match_tokens(&[TokenType::Colon]) {
-            Lox::error(
-                self.tokens[self.current].line,
-                format!("Expect : after ternary expression. got '{:?}' instead.", self.tokens[self.current])
-                );
+        if !self.match_tokens(&[TokenType::Colon]) {
+            self.error(self.peek(), "Expect : after ternary expression.".to_string());
             return None;
         }
 
-        let right = self.ternary();
-        if let None = right {
-            Lox::error(
-                self.tokens[self.current].line,
-                format!("Expect a expression after :. got '{:?}' instead.", self.tokens[self.current])
-                );
-            return None;
-        }
-        let right = right.unwrap();
+        let right = self.nested(Self::ternary)?;
 
-        return Some(Expr::Ternary(Box::new(condition.unwrap()), Box::new(left), Box::new(right)));
+        Some(Expr::Ternary(Box::new(condition), Box::new(left), Box::new(right)))
     }
 
     fn equality(&mut self) -> Option<Expr> {
-        let expr = self.comparison();
-        if let None = expr {
-            return None;
-        }
-        let mut expr = expr.unwrap();
+        let mut expr = self.comparison()?;
+        let mut height = expr.height();
 
         while self.match_tokens(&[TokenType::BangEqual, TokenType::EqualEqual]) {
             let operator = self.previous();
@@ -442,6 +668,7 @@ impl Parser {
             if right.is_none() {
                 break;
             }
+            height = self.chained(height, right.as_ref().unwrap().height())?;
             expr = Expr::Binary(
                 Box::new(expr),
                 operator,
@@ -449,15 +676,12 @@ impl Parser {
             );
         }
 
-        return Some(expr);
+        Some(expr)
     }
 
     fn comparison(&mut self) -> Option<Expr> {
-        let expr = self.term();
-        if let None = expr {
-            return None;
-        }
-        let mut expr = expr.unwrap();
+        let mut expr = self.term()?;
+        let mut height = expr.height();
 
         while self.match_tokens(&[TokenType::Greater, TokenType::GreaterEqual, TokenType::Less, TokenType::LessEqual]) {
             let operator = self.previous();
@@ -465,6 +689,7 @@ impl Parser {
             if right.is_none() {
                 break;
             }
+            height = self.chained(height, right.as_ref().unwrap().height())?;
 
             expr = Expr::Binary(
                 Box::new(expr),
@@ -473,39 +698,31 @@ impl Parser {
             );
         }
 
-        return Some(expr);
+        Some(expr)
     }
 
     fn term(&mut self) -> Option<Expr> {
-        let expr = self.factor();
-        if let None = expr {
-            return None;
-        }
-        let mut expr = expr.unwrap();
+        let mut expr = self.factor()?;
+        let mut height = expr.height();
 
         while self.match_tokens(&[TokenType::Minus, TokenType::Plus]) {
             let operator = self.previous();
-            let right = self.factor();
-            if right.is_none() {
-                return None;
-            }
+            let right = self.factor()?;
+            height = self.chained(height, right.height())?;
 
             expr = Expr::Binary(
                 Box::new(expr),
                 operator,
-                Box::new(right.unwrap())
+                Box::new(right)
             );
         }
 
-        return Some(expr);
+        Some(expr)
     }
 
     fn factor(&mut self) -> Option<Expr> {
-        let expr = self.unary();
-        if let None = expr {
-            return None;
-        }
-        let mut expr = expr.unwrap();
+        let mut expr = self.unary()?;
+        let mut height = expr.height();
 
         while self.match_tokens(&[TokenType::Slash, TokenType::Star]) {
             let operator = self.previous();
@@ -513,6 +730,7 @@ impl Parser {
             if right.is_none() {
                 break;
             }
+            height = self.chained(height, right.as_ref().unwrap().height())?;
 
             expr = Expr::Binary(
                 Box::new(expr),
@@ -521,62 +739,188 @@ impl Parser {
             );
         }
 
-        return Some(expr);
+        Some(expr)
     }
 
     fn unary(&mut self) -> Option<Expr> {
         if self.match_tokens(&[TokenType::Bang, TokenType::Minus]) {
             let operator = self.previous();
-            let right = self.unary();
-            if right.is_none() {
-                return None;
+            let right = self.nested(Self::unary)?;
+
+            return Some(Expr::Unary(operator, Box::new(right)));
+        }
+
+        self.call()
+    }
+
+    fn call(&mut self) -> Option<Expr> {
+        let mut expr = self.primary()?;
+        let mut height = expr.height();
+
+        loop {
+            if self.match_tokens(&[TokenType::LeftParen]) {
+                expr = self.finish_call(expr, &mut height)?;
+            } else if self.match_tokens(&[TokenType::Dot]) {
+                let name = self.consume(TokenType::Identifier, "Expect property name after '.'.".to_string())?;
+                height = self.chained(height, 0)?;
+                expr = Expr::Get(Box::new(expr), name);
+            } else if self.match_tokens(&[TokenType::LeftBracket]) {
+                let index = self.expression()?;
+                let bracket = self.consume(TokenType::RightBracket, "Expect ']' after index.".to_string())?;
+                height = self.chained(height, index.height())?;
+                expr = Expr::Index(Box::new(expr), bracket, Box::new(index));
+            } else {
+                break;
             }
+        }
 
-            return Some(Expr::Unary(operator, Box::new(right.unwrap())));
+        Some(expr)
+    }
+
+    /// Parses the arguments of a call to `callee`, whose tree is `height`
+    /// levels high, and updates `height` to the call's.
+    fn finish_call(&mut self, callee: Expr, height: &mut usize) -> Option<Expr> {
+        let mut arguments: Vec<Expr> = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if arguments.len() >= 255 {
+                    self.error(self.peek(), "Can't have more than 255 arguments.".to_string());
+                }
+                arguments.push(self.expression()?);
+
+                if !self.match_tokens(&[TokenType::Comma]) {
+                    break;
+                }
+            }
         }
 
-        return self.primary();
+        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.".to_string())?;
+        *height = self.chained(*height, arguments.iter().map(Expr::height).max().unwrap_or(0))?;
+
+        Some(Expr::Call(Box::new(callee), paren, arguments))
     }
 
     fn primary(&mut self) -> Option<Expr> {
         if self.match_tokens(&[TokenType::False]) {
-            return Some(Expr::Literal("false".to_string()));
+            return Some(Expr::Literal(Value::Bool(false), self.previous().span));
         }
 
         if self.match_tokens(&[TokenType::True]) {
-            return Some(Expr::Literal("true".to_string()));
+            return Some(Expr::Literal(Value::Bool(true), self.previous().span));
         }
 
         if self.match_tokens(&[TokenType::Nil]) {
-            return Some(Expr::Literal("nil".to_string()));
+            return Some(Expr::Literal(Value::Nil, self.previous().span));
         }
 
         if self.match_tokens(&[TokenType::Number, TokenType::String]) {
-            return Some(Expr::Literal(self.previous().lexeme));
+            let token = self.previous();
+            return Some(Expr::Literal(token.literal.unwrap_or(Value::Nil), token.span));
         }
 
         if self.match_tokens(&[TokenType::LeftParen]) {
-            let expr = self.expression().unwrap();
-            self.consume(TokenType::RightParen, "Expect ')' after expression.".to_string());
+            let start = self.previous().span;
+            let expr = self.expression()?;
+            self.consume(TokenType::RightParen, "Expect ')' after expression.".to_string())?;
+
+            return Some(Expr::Grouping(Box::new(expr), self.span_from(start)));
+        }
+
+        if self.match_tokens(&[TokenType::LeftBracket]) {
+            let start = self.previous().span;
+            let mut items: Vec<Expr> = Vec::new();
+            if !self.check(TokenType::RightBracket) {
+                loop {
+                    items.push(self.expression()?);
+                    if !self.match_tokens(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBracket, "Expect ']' after list items.".to_string())?;
 
-            return Some(Expr::Grouping(Box::new(expr)));
+            return Some(Expr::List(items, self.span_from(start)));
+        }
+
+        // `statement` takes a `{` that starts a statement as a block, so
+        // one reaching here is a map.
+        if self.match_tokens(&[TokenType::LeftBrace]) {
+            let start = self.previous().span;
+            let mut entries: Vec<(Expr, Token, Expr)> = Vec::new();
+            if !self.check(TokenType::RightBrace) {
+                loop {
+                    let key = self.expression()?;
+                    let colon = self.consume(TokenType::Colon, "Expect ':' after map key.".to_string())?;
+                    let value = self.expression()?;
+                    entries.push((key, colon, value));
+                    if !self.match_tokens(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBrace, "Expect '}' after map entries.".to_string())?;
+
+            return Some(Expr::Map(entries, self.span_from(start)));
+        }
+
+        if self.match_tokens(&[TokenType::Super]) {
+            let keyword = self.previous();
+            self.consume(TokenType::Dot, "Expect '.' after 'super'.".to_string())?;
+            let method = self.consume(TokenType::Identifier, "Expect superclass method name.".to_string())?;
+            return Some(Expr::Super(keyword, method, None));
+        }
+
+        if self.match_tokens(&[TokenType::This]) {
+            return Some(Expr::This(self.previous(), None));
         }
 
         if self.match_tokens(&[TokenType::Identifier]) {
-            return Some(Expr::Variable(self.previous()));
+            return Some(Expr::Variable(self.previous(), None));
         }
 
-        Lox::error(
-            self.tokens[self.current].line,
-            format!("Expect expression. got {} instead", self.tokens[self.current].lexeme)
-            );
-        return None;
+        self.error(self.peek(), "Expect expression.".to_string());
+        None
+    }
+
+    /// Parses one level deeper, reporting an error rather than overflowing
+    /// the stack once programs nest past `MAX_NESTING`.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Option<T>) -> Option<T> {
+        if self.nesting == MAX_NESTING {
+            self.error(self.peek(), "Too much nesting.".to_string());
+            return None;
+        }
+        self.nesting += 1;
+        let result = parse(self);
+        self.nesting -= 1;
+        result
+    }
+
+    /// The height of a node that a loop builds on top of one `height`
+    /// levels high, next to an operand `right` levels high, as each `+` in
+    /// `1 + 2 + 3` does. Such chains deepen the tree without the parser
+    /// recursing, so `nested` can't see them.
+    fn chained(&mut self, height: usize, right: usize) -> Option<usize> {
+        let height = height.max(right) + 1;
+        if self.nesting + height > MAX_NESTING {
+            self.error(self.peek(), "Too much nesting.".to_string());
+            return None;
+        }
+        Some(height)
     }
 
     fn consume(&mut self, type_: TokenType, msg: String) -> Option<Token> {
         if self.check(type_) { return Some(self.advance()); }
-        Lox::error(self.tokens[self.current].line, msg);
-        return None;
+        self.error(self.peek(), msg);
+        None
+    }
+
+    /// From `start` to the end of the last consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous().span)
+    }
+
+    fn error(&mut self, token: Token, message: String) {
+        self.errors.push(LoxError::Parse { token: Box::new(token), message });
     }
 
     fn synchronize(&mut self) {
@@ -588,13 +932,16 @@ impl Parser {
             }
 
             match self.peek().token_type {
-                // TokenType::Fun
-                TokenType::Var
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
                 | TokenType::For
                 | TokenType::If
                 | TokenType::While
-                | TokenType::Print => return,
-                // | TokenType::Return => return,
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Break
+                | TokenType::Continue => return,
                 _ => self.advance(),
             };
         }
@@ -608,19 +955,19 @@ impl Parser {
             }
         }
 
-        return false;
+        false
     }
 
     fn check(&mut self, type_: TokenType) -> bool {
         if self.is_at_end() { return false; }
 
-        return self.peek().token_type == type_;
+        self.peek().token_type == type_
     }
 
     fn advance(&mut self) -> Token {
         if !self.is_at_end() { self.current += 1; }
 
-        return self.previous();
+        self.previous()
     }
 
     fn is_at_end(&self) -> bool {
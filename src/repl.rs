@@ -0,0 +1,41 @@
+use crate::{
+    error::LoxError,
+    parser::Parser,
+    scanner::Scanner,
+    token::TokenType,
+};
+
+/// Whether `source` stops in the middle of a statement, so the REPL should
+/// keep reading lines instead of running it: a string is still open, there
+/// are more `(`, `[` or `{` than closing ones, or the parser ran out of
+/// tokens.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = match scanner.scan_tokens() {
+        Ok(tokens) => tokens.to_owned(),
+        Err(errors) => {
+            return errors.iter().any(|error| error.message() == "Unterminated string.");
+        },
+    };
+
+    let mut depth: isize = 0;
+    for token in &tokens {
+        match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => depth += 1,
+            TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace => depth -= 1,
+            _ => {},
+        }
+    }
+    if depth > 0 {
+        return true;
+    }
+
+    match Parser::new(tokens).parse() {
+        Ok(_) => false,
+        Err(errors) => errors.iter().any(at_end),
+    }
+}
+
+fn at_end(error: &LoxError) -> bool {
+    error.token().is_some_and(|token| token.token_type == TokenType::EOF)
+}
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+
+use crate::{error::{Label, LoxError}, parser::{Expr, FunctionDecl, Stmt}, token::{Span, Token}};
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Initializer,
+    Method,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+/// A variable declared in a local scope.
+#[derive(Clone, Copy)]
+struct Local {
+    /// True once the variable's initializer has been resolved.
+    defined: bool,
+    declared_at: Span,
+}
+
+/// Walks the parsed statements once before they run, working out how many
+/// scopes away every local variable lives and reporting static errors.
+pub struct Resolver {
+    /// One map per nested block.
+    scopes: Vec<HashMap<String, Local>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    /// Loops around the current statement within its function. The parser
+    /// checks `break` and `continue` too, but trees read from JSON skip it.
+    loop_depth: usize,
+    errors: Vec<LoxError>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: Vec::new(),
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            loop_depth: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Annotates every variable access in `statements` with its scope depth.
+    /// Returns every static error found along the way.
+    pub fn resolve(&mut self, statements: &mut [Stmt]) -> Result<(), Vec<LoxError>> {
+        for stmt in statements.iter_mut() {
+            self.resolve_stmt(stmt);
+        }
+
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors));
+        }
+        Ok(())
+    }
+
+    fn resolve_block(&mut self, statements: &mut [Stmt]) {
+        for stmt in statements.iter_mut() {
+            self.resolve_stmt(stmt);
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Expression(expr) | Stmt::Print(expr, _) => self.resolve_expr(expr),
+            Stmt::Let(name, initializer) => {
+                self.declare(name);
+                if let Some(initializer) = initializer {
+                    self.resolve_expr(initializer);
+                }
+                self.define(name);
+            },
+            Stmt::Block(statements, _) => {
+                self.begin_scope();
+                self.resolve_block(statements);
+                self.end_scope();
+            },
+            Stmt::If(cond, then, else_, _) => {
+                self.resolve_expr(cond);
+                self.resolve_stmt(then);
+                if let Some(else_) = else_.as_mut() {
+                    self.resolve_stmt(else_);
+                }
+            },
+            Stmt::While(cond, body, increment, _) => {
+                self.resolve_expr(cond);
+                self.loop_depth += 1;
+                self.resolve_stmt(body);
+                self.loop_depth -= 1;
+                if let Some(increment) = increment {
+                    self.resolve_expr(increment);
+                }
+            },
+            Stmt::Break(keyword) | Stmt::Continue(keyword) => {
+                if self.loop_depth == 0 {
+                    self.error(keyword, &format!("Can't use '{}' outside of a loop.", keyword.lexeme));
+                }
+            },
+            Stmt::Function(declaration) => {
+                self.declare(&declaration.name);
+                self.define(&declaration.name);
+                self.resolve_function(declaration, FunctionType::Function);
+            },
+            Stmt::Return(keyword, value) => {
+                if self.current_function == FunctionType::None {
+                    self.error(keyword, "Can't return from top-level code.");
+                }
+                if let Some(value) = value {
+                    if self.current_function == FunctionType::Initializer {
+                        self.error(keyword, "Can't return a value from an initializer.");
+                    }
+                    self.resolve_expr(value);
+                }
+            },
+            Stmt::Class(name, superclass, methods) => {
+                let enclosing_class = self.current_class;
+                self.current_class = ClassType::Class;
+
+                self.declare(name);
+                self.define(name);
+
+                if let Some(superclass) = superclass {
+                    if let Expr::Variable(superclass_name, _) = superclass {
+                        if superclass_name.lexeme == name.lexeme {
+                            self.error(superclass_name, "A class can't inherit from itself.");
+                        }
+                    }
+
+                    self.current_class = ClassType::Subclass;
+                    self.resolve_expr(superclass);
+
+                    self.begin_scope();
+                    if let Some(scope) = self.scopes.last_mut() {
+                        scope.insert("super".to_string(), Local { defined: true, declared_at: name.span });
+                    }
+                }
+
+                self.begin_scope();
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert("this".to_string(), Local { defined: true, declared_at: name.span });
+                }
+
+                for method in methods.iter_mut() {
+                    let kind = if method.name.lexeme == "init" {
+                        FunctionType::Initializer
+                    } else {
+                        FunctionType::Method
+                    };
+                    self.resolve_function(method, kind);
+                }
+
+                self.end_scope();
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+                self.current_class = enclosing_class;
+            },
+        }
+    }
+
+    fn resolve_function(&mut self, declaration: &mut FunctionDecl, kind: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = kind;
+        let enclosing_loops = std::mem::replace(&mut self.loop_depth, 0);
+
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_block(&mut declaration.body);
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+        self.loop_depth = enclosing_loops;
+    }
+
+    fn resolve_expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Binary(left, _, right) | Expr::Logical(left, _, right) => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            },
+            Expr::Grouping(expr, _) | Expr::Unary(_, expr) => self.resolve_expr(expr),
+            Expr::Literal(..) => {},
+            Expr::Ternary(cond, left, right) => {
+                self.resolve_expr(cond);
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            },
+            Expr::Variable(name, depth) => {
+                let declaring = self.scopes
+                    .last()
+                    .and_then(|scope| scope.get(&name.lexeme))
+                    .filter(|local| !local.defined)
+                    .map(|local| local.declared_at);
+                if let Some(declared_at) = declaring {
+                    self.error_with_label(
+                        name,
+                        "Can't read local variable in its own initializer.",
+                        Label { span: declared_at, message: "variable declared here".to_string() }
+                    );
+                }
+                *depth = self.resolve_local(name);
+            },
+            Expr::Assign(name, value, depth) => {
+                self.resolve_expr(value);
+                *depth = self.resolve_local(name);
+            },
+            Expr::Call(callee, _, arguments) => {
+                self.resolve_expr(callee);
+                for argument in arguments.iter_mut() {
+                    self.resolve_expr(argument);
+                }
+            },
+            Expr::Get(object, _) => self.resolve_expr(object),
+            Expr::Set(object, _, value) => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            },
+            Expr::This(keyword, depth) => {
+                if self.current_class == ClassType::None {
+                    self.error(keyword, "Can't use 'this' outside of a class.");
+                    return;
+                }
+                *depth = self.resolve_local(keyword);
+            },
+            Expr::Super(keyword, _, depth) => {
+                match self.current_class {
+                    ClassType::None => self.error(keyword, "Can't use 'super' outside of a class."),
+                    ClassType::Class => self.error(keyword, "Can't use 'super' in a class with no superclass."),
+                    ClassType::Subclass => {},
+                }
+                *depth = self.resolve_local(keyword);
+            },
+            Expr::List(items, _) => {
+                for item in items {
+                    self.resolve_expr(item);
+                }
+            },
+            Expr::Map(entries, _) => {
+                for (key, _, value) in entries {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
+                }
+            },
+            Expr::Index(object, _, index) => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            },
+            Expr::SetIndex(object, _, index, value) => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+                self.resolve_expr(value);
+            },
+        }
+    }
+
+    /// How many scopes out `name` was declared, or `None` for globals.
+    fn resolve_local(&self, name: &Token) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&name.lexeme))
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else { return; };
+        if let Some(previous) = scope.get(&name.lexeme) {
+            let label = Label { span: previous.declared_at, message: "first declared here".to_string() };
+            self.error_with_label(name, "Already a variable with this name in this scope.", label);
+            return;
+        }
+
+        scope.insert(name.lexeme.clone(), Local { defined: false, declared_at: name.span });
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.entry(name.lexeme.clone())
+                .or_insert(Local { defined: false, declared_at: name.span })
+                .defined = true;
+        }
+    }
+
+    fn error(&mut self, token: &Token, msg: &str) {
+        self.errors.push(LoxError::Resolve { token: Box::new(token.clone()), message: msg.to_string(), labels: Vec::new() });
+    }
+
+    fn error_with_label(&mut self, token: &Token, msg: &str, label: Label) {
+        self.errors.push(LoxError::Resolve { token: Box::new(token.clone()), message: msg.to_string(), labels: vec![label] });
+    }
+}
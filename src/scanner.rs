@@ -1,157 +1,214 @@
 use std::collections::HashMap;
 
-use crate::{token::{
-    Token, TokenType
-}, Lox};
+use crate::{error::LoxError, token::{
+    Span, Token, TokenType
+}, value::Value};
 
+/// Every reserved word and the token it scans to.
+pub const KEYWORDS: &[(&str, TokenType)] = &[
+    ("and", TokenType::And),
+    ("while", TokenType::While),
+    ("var", TokenType::Var),
+    ("true", TokenType::True),
+    ("print", TokenType::Print),
+    ("or", TokenType::Or),
+    ("nil", TokenType::Nil),
+    ("if", TokenType::If),
+    ("for", TokenType::For),
+    ("false", TokenType::False),
+    ("else", TokenType::Else),
+    ("fun", TokenType::Fun),
+    ("return", TokenType::Return),
+    ("class", TokenType::Class),
+    ("this", TokenType::This),
+    ("super", TokenType::Super),
+    ("break", TokenType::Break),
+    ("continue", TokenType::Continue),
+];
+
+/// Turns source text into tokens. `start` and `current` are byte offsets.
 pub struct Scanner {
     start: usize,
     current: usize,
     line: usize,
+    /// The 1-based column of `current`, counted in characters as the
+    /// scanner advances rather than from the start of the line each time.
+    column: usize,
+    /// Line and column of the token being scanned.
+    start_line: usize,
+    start_column: usize,
     source: String,
     tokens: Vec<Token>,
-    keywords: HashMap<String, TokenType>
+    keywords: HashMap<String, TokenType>,
+    errors: Vec<LoxError>,
+    /// Whether comments become `Comment` tokens instead of being skipped.
+    keep_comments: bool,
+    /// Added to every span, for source that follows earlier input.
+    offset: usize,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Self {
-        let mut keywords: HashMap<String, TokenType> = HashMap::new();
-        keywords.insert("and".to_string(), TokenType::And);
-        keywords.insert("while".to_string(), TokenType::While);
-        keywords.insert("var".to_string(), TokenType::Var);
-        keywords.insert("true".to_string(), TokenType::True);
-        keywords.insert("print".to_string(), TokenType::Print);
-        keywords.insert("or".to_string(), TokenType::Or);
-        keywords.insert("nil".to_string(), TokenType::Nil);
-        keywords.insert("if".to_string(), TokenType::If);
-        keywords.insert("for".to_string(), TokenType::For);
-        keywords.insert("false".to_string(), TokenType::False);
-        keywords.insert("else".to_string(), TokenType::Else);
+        let keywords = KEYWORDS
+            .iter()
+            .map(|&(name, token_type)| (name.to_string(), token_type))
+            .collect();
 
         Scanner {
             source, start: 0,
             current: 0, line: 1,
+            column: 1,
+            start_line: 1,
+            start_column: 1,
             tokens: Vec::new(),
-            keywords
+            keywords,
+            errors: Vec::new(),
+            keep_comments: false,
+            offset: 0,
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Option<&Vec<Token>> {
+    /// A scanner that keeps comments as `Comment` tokens, for tools like the
+    /// formatter that have to put them back. The parser doesn't accept them.
+    pub fn with_comments(source: String) -> Self {
+        Scanner { keep_comments: true, ..Scanner::new(source) }
+    }
+
+    /// A scanner for source that comes `offset` bytes into a longer text,
+    /// such as one input of a REPL session, so that its spans point into
+    /// that text. Lines still count from 1.
+    pub fn with_offset(source: String, offset: usize) -> Self {
+        Scanner { offset, ..Scanner::new(source) }
+    }
+
+    /// Scans the whole source, carrying on past bad characters so every
+    /// scan error is reported at once.
+    pub fn scan_tokens(&mut self) -> Result<&Vec<Token>, Vec<LoxError>> {
         while !self.is_at_end() {
             self.start = self.current;
-            if let Err(_) = self.scan_token() {
-                return None;
-            }
+            self.start_line = self.line;
+            self.start_column = self.column;
+            let _ = self.scan_token();
+        }
+
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors));
         }
 
+        let end = self.offset + self.source.len();
         self.tokens.push(
             Token::new(TokenType::EOF, "\0".to_string(), self.line)
+                .at(self.column, Span::new(end, end))
         );
 
-        return Some(&self.tokens);
+        Ok(&self.tokens)
     }
 
     fn scan_token(&mut self) -> Result<(), ()> {
         let c = self.advance();
         match c {
             '(' => {
-                self.add_token(TokenType::LeftParen, "(".to_string(), self.line)
+                self.add_token(TokenType::LeftParen, "(".to_string())
             },
             ')' => {
-                self.add_token(TokenType::RightParen, ")".to_string(), self.line)
+                self.add_token(TokenType::RightParen, ")".to_string())
             },
             '{' => {
-                self.add_token(TokenType::LeftBrace, "{".to_string(), self.line)
+                self.add_token(TokenType::LeftBrace, "{".to_string())
             },
             '}' => {
-                self.add_token(TokenType::RightBrace, "}".to_string(), self.line)
+                self.add_token(TokenType::RightBrace, "}".to_string())
+            },
+            '[' => {
+                self.add_token(TokenType::LeftBracket, "[".to_string())
+            },
+            ']' => {
+                self.add_token(TokenType::RightBracket, "]".to_string())
             },
             ',' => {
-                self.add_token(TokenType::Comma, ",".to_string(), self.line)
+                self.add_token(TokenType::Comma, ",".to_string())
             },
             '.' => {
-                self.add_token(TokenType::Dot, ".".to_string(), self.line)
+                self.add_token(TokenType::Dot, ".".to_string())
             },
             '-' => {
-                self.add_token(TokenType::Minus, "-".to_string(), self.line)
+                self.add_token(TokenType::Minus, "-".to_string())
             },
             '+' => {
-                self.add_token(TokenType::Plus, "+".to_string(), self.line)
+                self.add_token(TokenType::Plus, "+".to_string())
             },
             ';' => {
-                self.add_token(TokenType::Semicolon, ";".to_string(), self.line)
+                self.add_token(TokenType::Semicolon, ";".to_string())
             },
             '*' => {
-                self.add_token(TokenType::Star, "*".to_string(), self.line)
+                self.add_token(TokenType::Star, "*".to_string())
             },
             '?' => {
-                self.add_token(TokenType::Qmark, "?".to_string(), self.line)
+                self.add_token(TokenType::Qmark, "?".to_string())
             },
             ':' => {
-                self.add_token(TokenType::Colon, ":".to_string(), self.line)
+                self.add_token(TokenType::Colon, ":".to_string())
             },
             '/' => {
                 if self.match_lexeme('/') {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
-                    Ok(())
+                    self.add_comment()
                 } else  if self.match_lexeme('*') {
-                    while self.peek() != '*' && self.peek_next() != '/' && !self.is_at_end() {
-                        // if self.peek() == '\n' { self.line += 1; }
-                        self.advance();
+                    while !(self.is_at_end() || self.peek() == '*' && self.peek_next() == '/') {
+                        if self.advance() == '\n' { self.new_line(); }
                     }
                     self.advance();
                     self.advance();
-                    Ok(())
+                    self.add_comment()
                 } else {
-                    self.add_token(TokenType::Slash, "/".to_string(), self.line)
+                    self.add_token(TokenType::Slash, "/".to_string())
                 }
             },
             '=' => {
                 if self.match_lexeme('=') {
-                    self.add_token(TokenType::EqualEqual, "==".to_string(), self.line)
+                    self.add_token(TokenType::EqualEqual, "==".to_string())
                 } else {
-                    self.add_token(TokenType::Equal, "=".to_string(), self.line)
+                    self.add_token(TokenType::Equal, "=".to_string())
                 }
             },
             '!' => {
                 if self.match_lexeme('=') {
-                    self.add_token(TokenType::BangEqual, "!=".to_string(), self.line)
+                    self.add_token(TokenType::BangEqual, "!=".to_string())
                 } else {
-                    self.add_token(TokenType::Bang, "!".to_string(), self.line)
+                    self.add_token(TokenType::Bang, "!".to_string())
                 }
             },
             '<' => {
                 if self.match_lexeme('=') {
-                    self.add_token(TokenType::LessEqual, "<=".to_string(), self.line)
+                    self.add_token(TokenType::LessEqual, "<=".to_string())
                 } else {
-                    self.add_token(TokenType::Less, "<".to_string(), self.line)
+                    self.add_token(TokenType::Less, "<".to_string())
                 }
             },
             '>' => {
                 if self.match_lexeme('=') {
-                    self.add_token(TokenType::GreaterEqual, ">=".to_string(), self.line)
+                    self.add_token(TokenType::GreaterEqual, ">=".to_string())
                 } else {
-                    self.add_token(TokenType::Greater, ">".to_string(), self.line)
+                    self.add_token(TokenType::Greater, ">".to_string())
                 }
             },
             '\0' => {
-                self.add_token(TokenType::EOF, "\0".to_string(), self.line)
+                self.add_token(TokenType::EOF, "\0".to_string())
             },
             ' ' | '\t' | '\r' => { Ok(()) },
             '`' => { self.string('`') },
             '"' => { self.string('"') },
             '\'' => { self.string('\'') },
-            '\n' => { self.line += 1; Ok(()) },
+            '\n' => { self.new_line(); Ok(()) },
             _ => {
                 if Self::is_digit(c) {
                     self.number()
                 } else if Self::is_alpha(c) {
                     self.identifier()
                 }else {
-                    Lox::error(self.line, "Unexpected Character.".to_string());
-                    return Err(());
+                    self.error("Unexpected Character.")
                 }
             }
         }
@@ -165,7 +222,7 @@ impl Scanner {
         let token_type = self.keywords.get(&lexeme)
             .unwrap_or(&TokenType::Identifier);
 
-        self.add_token(*token_type, lexeme, self.line)
+        self.add_token(*token_type, lexeme)
     }
 
     fn number(&mut self) -> Result<(), ()> {
@@ -181,52 +238,91 @@ impl Scanner {
         }
 
         let lexeme = self.source[self.start..self.current].to_string();
+        let value = lexeme.parse::<f64>().unwrap();
 
-        self.add_token(TokenType::Number, lexeme, self.line)
+        self.add_literal_token(TokenType::Number, lexeme, Value::Number(value))
     }
 
     fn string(&mut self, ch: char) -> Result<(), ()> {
         while self.peek() != ch && !self.is_at_end() {
-            if self.peek() == '\n' { self.line += 1; }
-            self.advance();
+            if self.advance() == '\n' { self.new_line(); }
         }
 
         if self.is_at_end() {
-            Lox::error(self.line, "Unterminated string.".to_string());
-            return Err(());
+            return self.error("Unterminated string.");
         }
 
         self.advance();
-        let lexeme = self.source[self.start+1..self.current-1].to_string();
+        let lexeme = self.source[self.start..self.current].to_string();
+        let value = self.source[self.start+1..self.current-1].to_string();
+
+        self.add_literal_token(TokenType::String, lexeme, Value::String(value))
+    }
 
-        self.add_token(TokenType::String, lexeme, self.line)
+    fn error(&mut self, message: &str) -> Result<(), ()> {
+        self.errors.push(LoxError::Scan {
+            line: self.start_line,
+            column: self.start_column,
+            span: self.span(),
+            message: message.to_string()
+        });
+        Err(())
+    }
+
+    /// The span of the token being scanned.
+    fn span(&self) -> Span {
+        Span::new(self.offset + self.start, self.offset + self.current)
+    }
+
+    /// Called after consuming a newline.
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.column = 1;
     }
 
     fn advance(&mut self) -> char {
-        self.current += 1;
-        self.source
-            .chars()
-            .nth(self.current - 1)
-            .unwrap_or('\0')
+        let c = self.peek();
+        if !self.is_at_end() {
+            self.current += c.len_utf8();
+            self.column += 1;
+        }
+        c
+    }
+
+    fn add_token(&mut self, token_type: TokenType, lexeme: String) -> Result<(), ()> {
+        let token = Token::new(token_type, lexeme, self.start_line)
+            .at(self.start_column, self.span());
+        self.tokens.push(token);
+        Ok(())
+    }
+
+    fn add_comment(&mut self) -> Result<(), ()> {
+        if !self.keep_comments {
+            return Ok(());
+        }
+        let text = self.source[self.start..self.current].trim_end().to_string();
+        self.add_token(TokenType::Comment, text)
     }
 
-    fn add_token(
+    fn add_literal_token(
         &mut self, token_type: TokenType,
-        lexeme: String, line: usize
+        lexeme: String, literal: Value
     ) -> Result<(), ()> {
-        let token = Token::new(token_type, lexeme, line);
+        let token = Token::with_literal(token_type, lexeme, literal, self.start_line)
+            .at(self.start_column, self.span());
         self.tokens.push(token);
         Ok(())
     }
 
     fn match_lexeme(&mut self, ch: char) -> bool {
         if self.is_at_end() { return false; }
-        if self.source.chars().nth(self.current).unwrap() != ch {
+        if self.peek() != ch {
             return false;
         }
 
-        self.current += 1;
-        return true;
+        self.current += ch.len_utf8();
+        self.column += 1;
+        true
     }
 
     fn is_at_end(&self) -> bool {
@@ -246,16 +342,16 @@ impl Scanner {
     }
 
     fn peek(&self) -> char {
-        self.source
+        self.source[self.current..]
             .chars()
-            .nth(self.current)
+            .next()
             .unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        self.source
+        self.source[self.current..]
             .chars()
-            .nth(self.current + 1)
+            .nth(1)
             .unwrap_or('\0')
     }
 }
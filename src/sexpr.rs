@@ -0,0 +1,95 @@
+use crate::{parser::{Expr, FunctionDecl, Stmt}, value::Value};
+
+/// Prints `statements` as s-expressions, one top-level statement per line:
+/// `(print (+ 1 2))`, `(var x 1)`, `(while (< i 3) (block ...))`. A `for`
+/// loop with an increment is `(for cond increment body)`.
+pub fn program(statements: &[Stmt]) -> String {
+    statements.iter().map(|stmt| stmt_sexpr(stmt) + "\n").collect()
+}
+
+fn stmt_sexpr(stmt: &Stmt) -> String {
+    match stmt {
+        Stmt::Expression(expr) => expr_sexpr(expr),
+        Stmt::Print(expr, _) => format!("(print {})", expr_sexpr(expr)),
+        Stmt::Let(name, initializer) => match initializer {
+            Some(initializer) => format!("(var {} {})", name.lexeme, expr_sexpr(initializer)),
+            None => format!("(var {})", name.lexeme),
+        },
+        Stmt::Block(statements, _) => list("block", statements.iter().map(stmt_sexpr)),
+        Stmt::If(cond, then, else_, _) => match else_.as_ref() {
+            Some(else_) => format!("(if {} {} {})", expr_sexpr(cond), stmt_sexpr(then), stmt_sexpr(else_)),
+            None => format!("(if {} {})", expr_sexpr(cond), stmt_sexpr(then)),
+        },
+        Stmt::While(cond, body, increment, _) => match increment {
+            Some(increment) => {
+                format!("(for {} {} {})", expr_sexpr(cond), expr_sexpr(increment), stmt_sexpr(body))
+            },
+            None => format!("(while {} {})", expr_sexpr(cond), stmt_sexpr(body)),
+        },
+        Stmt::Function(declaration) => function_sexpr(declaration),
+        Stmt::Return(_, value) => match value {
+            Some(value) => format!("(return {})", expr_sexpr(value)),
+            None => "(return)".to_string(),
+        },
+        Stmt::Class(name, superclass, methods) => {
+            let head = match superclass {
+                Some(superclass) => format!("class {} (< {})", name.lexeme, expr_sexpr(superclass)),
+                None => format!("class {}", name.lexeme),
+            };
+            list(&head, methods.iter().map(function_sexpr))
+        },
+        Stmt::Break(_) => "(break)".to_string(),
+        Stmt::Continue(_) => "(continue)".to_string(),
+    }
+}
+
+fn function_sexpr(declaration: &FunctionDecl) -> String {
+    let params: Vec<&str> = declaration.params.iter().map(|param| param.lexeme.as_str()).collect();
+    let head = format!("fun {} ({})", declaration.name.lexeme, params.join(" "));
+    list(&head, declaration.body.iter().map(stmt_sexpr))
+}
+
+fn expr_sexpr(expr: &Expr) -> String {
+    match expr {
+        Expr::Binary(left, op, right) | Expr::Logical(left, op, right) => {
+            format!("({} {} {})", op.lexeme, expr_sexpr(left), expr_sexpr(right))
+        },
+        Expr::Grouping(expr, _) => format!("(group {})", expr_sexpr(expr)),
+        Expr::Literal(Value::String(s), _) => format!("{:?}", s),
+        Expr::Literal(value, _) => value.to_string(),
+        Expr::Unary(op, right) => format!("({} {})", op.lexeme, expr_sexpr(right)),
+        Expr::Variable(name, _) => name.lexeme.clone(),
+        Expr::Ternary(cond, left, right) => {
+            format!("(? {} {} {})", expr_sexpr(cond), expr_sexpr(left), expr_sexpr(right))
+        },
+        Expr::Assign(name, value, _) => format!("(= {} {})", name.lexeme, expr_sexpr(value)),
+        Expr::Call(callee, _, arguments) => {
+            list(&format!("call {}", expr_sexpr(callee)), arguments.iter().map(expr_sexpr))
+        },
+        Expr::Get(object, name) => format!("(. {} {})", expr_sexpr(object), name.lexeme),
+        Expr::Set(object, name, value) => {
+            format!("(.= {} {} {})", expr_sexpr(object), name.lexeme, expr_sexpr(value))
+        },
+        Expr::This(_, _) => "this".to_string(),
+        Expr::Super(_, method, _) => format!("(super {})", method.lexeme),
+        Expr::List(items, _) => list("list", items.iter().map(expr_sexpr)),
+        Expr::Map(entries, _) => list("map", entries.iter().map(|(key, _, value)| {
+            format!("({} {})", expr_sexpr(key), expr_sexpr(value))
+        })),
+        Expr::Index(object, _, index) => format!("([] {} {})", expr_sexpr(object), expr_sexpr(index)),
+        Expr::SetIndex(object, _, index, value) => {
+            format!("([]= {} {} {})", expr_sexpr(object), expr_sexpr(index), expr_sexpr(value))
+        },
+    }
+}
+
+/// `(head item item ...)`.
+fn list(head: &str, items: impl Iterator<Item = String>) -> String {
+    let mut out = format!("({}", head);
+    for item in items {
+        out.push(' ');
+        out.push_str(&item);
+    }
+    out.push(')');
+    out
+}
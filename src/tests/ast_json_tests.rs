@@ -0,0 +1,133 @@
+#![cfg(test)]
+
+use crate::{
+    ast_json, interpreter::Interpreter, json::{self, Json}, parser::{Parser, Stmt}, resolver::Resolver,
+    scanner::Scanner,
+};
+
+fn parse(input: &str) -> Vec<Stmt> {
+    let mut scanner = Scanner::new(input.to_string());
+    let tokens = scanner.scan_tokens().unwrap();
+    Parser::new(tokens.to_vec()).parse().unwrap()
+}
+
+fn to_json(input: &str) -> String {
+    let mut statements = parse(input);
+    Resolver::new().resolve(&mut statements).unwrap();
+    ast_json::program(&statements).to_string()
+}
+
+fn read(text: &str) -> Result<Vec<Stmt>, String> {
+    ast_json::read_program(&json::parse(text)?)
+}
+
+#[test]
+fn test_program() {
+    assert_eq!(
+        to_json("print -x;"),
+        concat!(
+            r#"{"version": 1, "statements": [{"kind": "Print", "span": [0, 9], "expression": "#,
+            r#"{"kind": "Unary", "span": [6, 8], "#,
+            r#""operator": {"type": "Minus", "lexeme": "-", "line": 1, "column": 7, "span": [6, 7]}, "#,
+            r#""right": {"kind": "Variable", "span": [7, 8], "#,
+            r#""name": {"type": "Identifier", "lexeme": "x", "line": 1, "column": 8, "span": [7, 8]}, "#,
+            r#""depth": null}}}]}"#,
+        )
+    );
+}
+
+#[test]
+fn test_optional_children_and_depth() {
+    let json = to_json("{ var a; a = nil; }");
+    assert!(json.contains(r#""initializer": null"#));
+    assert!(json.contains(r#""kind": "Assign""#));
+    assert!(json.contains(r#""depth": 0"#));
+    assert!(json.contains(r#""value": null"#));
+}
+
+#[test]
+fn test_json_output() {
+    let document = Json::object([
+        ("text", Json::String("say \"hi\"\n\\".to_string())),
+        ("list", Json::Array(vec![Json::Number(1.5), Json::Bool(true), Json::Array(vec![])])),
+    ]);
+    assert_eq!(document.to_string(), r#"{"text": "say \"hi\"\n\\", "list": [1.5, true, []]}"#);
+    assert_eq!(document.pretty(), "{\n  \"text\": \"say \\\"hi\\\"\\n\\\\\",\n  \"list\": [\n    1.5,\n    true,\n    []\n  ]\n}");
+}
+
+#[test]
+fn test_read_back() {
+    let input = r#"
+        var a = "tab\there";
+        fun f(x, y) { if (x and !y) return x; else { return -1; } }
+        class A { m() { return this.v; } }
+        class B < A { init() { this.v = nil; super.m(); } }
+        for (var i = 0; i < 3; i = i + 1) print (i > 1 ? (f(i, false)) : a);
+        while (true) { if (a) continue; break; }
+        var xs = [1, [2]]; xs[0] = xs[1][0];
+        var m = {"a": {}, 1: xs}; m["b"] = m["a"];
+    "#;
+    let statements = parse(input);
+    let text = ast_json::program(&statements).pretty();
+    assert_eq!(read(&text), Ok(statements));
+}
+
+#[test]
+fn test_read_without_positions() {
+    let text = r#"{"version": 1, "statements": [
+        {"kind": "Print", "expression": {"kind": "Binary",
+            "left": {"kind": "Literal", "value": 40},
+            "operator": {"lexeme": "+"},
+            "right": {"kind": "Literal", "value": 2}}}
+    ]}"#;
+    let mut statements = read(text).unwrap();
+    Resolver::new().resolve(&mut statements).unwrap();
+    assert!(Interpreter::with_output(Box::new(std::io::sink())).interpret(statements).is_ok());
+}
+
+#[test]
+fn test_loop_jumps_are_checked_when_read() {
+    // Without the parser to catch it, a stray `break` must still not run.
+    let text = r#"{"version": 1, "statements": [{"kind": "Break", "keyword": {"lexeme": "break"}}]}"#;
+    let mut statements = read(text).unwrap();
+    let errors = Resolver::new().resolve(&mut statements).unwrap_err();
+    assert_eq!(errors[0].message(), "Can't use 'break' outside of a loop.");
+}
+
+#[test]
+fn test_deep_documents_are_rejected() {
+    let deep = format!("{}1{}", "[".repeat(100_000), "]".repeat(100_000));
+    assert_eq!(json::parse(&deep), Err("too deeply nested at byte 1024".to_string()));
+
+    let fits = format!("{}1{}", "[".repeat(1000), "]".repeat(1000));
+    assert!(json::parse(&fits).is_ok());
+}
+
+#[test]
+fn test_read_errors() {
+    assert_eq!(read(r#"{"version": 2, "statements": []}"#), Err("unsupported schema version 2".to_string()));
+    assert_eq!(read(r#"{"version": 1, "statements": [{"kind": "Loop"}]}"#), Err("unknown statement kind 'Loop'".to_string()));
+    assert_eq!(read(r#"{"version": 1, "statements": [{"kind": "Print"}]}"#), Err("Print is missing 'expression'".to_string()));
+    assert_eq!(
+        read(r#"{"version": 1, "statements": [{"kind": "Expression", "expression": {"kind": "Variable", "name": {"lexeme": "a b"}}}]}"#),
+        Err("'a b' is not a single token".to_string())
+    );
+    assert_eq!(read(r#"{"version": 1, "statements": [}"#), Err("expected a value at byte 30".to_string()));
+    assert_eq!(
+        read(r#"{"version": 1, "statements": [{"kind": "Expression", "expression": {"kind": "Unary", "operator": {"lexeme": "+"}, "right": {"kind": "Literal", "value": 1}}}]}"#),
+        Err("'+' is not a Unary operator".to_string())
+    );
+    assert_eq!(
+        read(r#"{"version": 1, "statements": [{"kind": "Expression", "expression": {"kind": "Variable", "name": {"lexeme": "a", "line": 0}}}]}"#),
+        Err("a token's 'line' counts from 1".to_string())
+    );
+    // Errors name the type of a bad value rather than printing all of it.
+    assert_eq!(
+        read(r#"{"version": 1, "statements": [{"kind": "Print", "expression": [{"kind": "Literal", "value": 1}]}]}"#),
+        Err("expected a node with a 'kind', found an array".to_string())
+    );
+    assert_eq!(
+        read(r#"{"version": 1, "statements": [{"kind": "Print", "expression": {"kind": "Literal", "value": 1, "span": ["0", 1]}}]}"#),
+        Err("expected a whole number, found a string".to_string())
+    );
+}
@@ -0,0 +1,60 @@
+#![cfg(test)]
+
+use crate::{cli::{parse, AstFormat, Cli, Command, Script}, Backend};
+
+fn parse_args(args: &[&str]) -> Result<Cli, String> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    parse(&args)
+}
+
+#[test]
+fn test_default_commands() {
+    assert_eq!(parse_args(&[]).unwrap().command, Command::Repl);
+    assert_eq!(parse_args(&["main.lox"]).unwrap().command, Command::Run(Script::File("main.lox".to_string())));
+    assert_eq!(parse_args(&["-"]).unwrap().command, Command::Run(Script::Stdin));
+    assert_eq!(parse_args(&["-e", "print 1;"]).unwrap().command, Command::Run(Script::Inline("print 1;".to_string())));
+    assert_eq!(parse_args(&["--version", "main.lox"]).unwrap().command, Command::Version);
+}
+
+#[test]
+fn test_subcommands_and_options() {
+    let cli = parse_args(&["--backend", "vm", "ast", "main.lox", "--format", "json", "-o", "out.json"]).unwrap();
+    assert_eq!(cli.backend, Backend::Vm);
+    assert_eq!(cli.command, Command::Ast {
+        script: Script::File("main.lox".to_string()),
+        format: AstFormat::Json,
+        output: Some("out.json".to_string()),
+    });
+
+    let cli = parse_args(&["run", "--compile", "main.loxc", "main.lox"]).unwrap();
+    assert_eq!(cli.compile, Some("main.loxc".to_string()));
+    // A file named like a command can still be given after one.
+    assert_eq!(parse_args(&["check", "fmt"]).unwrap().command, Command::Check(Script::File("fmt".to_string())));
+
+    let cli = parse_args(&["fmt", "--check", "-"]).unwrap();
+    assert_eq!(cli.command, Command::Fmt { script: Script::Stdin, check: true });
+}
+
+#[test]
+fn test_script_arguments() {
+    let cli = parse_args(&["--backend", "vm", "main.lox", "a", "--backend", "-e"]).unwrap();
+    assert_eq!(cli.backend, Backend::Vm);
+    assert_eq!(cli.command, Command::Run(Script::File("main.lox".to_string())));
+    assert_eq!(cli.args, vec!["a", "--backend", "-e"]);
+
+    let cli = parse_args(&["run", "-e", "print args;", "x"]).unwrap();
+    assert_eq!(cli.args, vec!["x"]);
+}
+
+#[test]
+fn test_usage_errors() {
+    assert!(parse_args(&["--backend", "jit", "main.lox"]).is_err());
+    assert!(parse_args(&["--backend"]).is_err());
+    assert!(parse_args(&["tokens"]).is_err());
+    assert!(parse_args(&["repl", "main.lox"]).is_err());
+    assert!(parse_args(&["check", "--format", "json", "main.lox"]).is_err());
+    assert!(parse_args(&["check", "-e", "print 1;", "main.lox"]).is_err());
+    assert!(parse_args(&["tokens", "main.lox", "extra"]).is_err());
+    assert!(parse_args(&["--verbose"]).is_err());
+    assert!(parse_args(&["--check", "main.lox"]).is_err());
+}
@@ -0,0 +1,94 @@
+#![cfg(test)]
+
+use crate::{
+    diagnostic,
+    error::LoxError,
+    parser::Parser,
+    resolver::Resolver,
+    scanner::Scanner,
+    token::Span,
+};
+
+fn resolve_errors(source: &str) -> Vec<LoxError> {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().unwrap();
+    let mut statements = Parser::new(tokens.to_vec()).parse().unwrap();
+    Resolver::new().resolve(&mut statements).unwrap_err()
+}
+
+#[test]
+fn test_render_with_secondary_label() {
+    let source = "{\n  var name = 1;\n  var name = 2;\n}\n";
+    let errors = resolve_errors(source);
+
+    assert_eq!(diagnostic::render(&errors[0], "test.lox", source), "\
+error[resolve]
+ --> test.lox:3:7
+  |
+2 |   var name = 1;
+  |       ---- first declared here
+3 |   var name = 2;
+  |       ^^^^ Already a variable with this name in this scope.
+");
+}
+
+#[test]
+fn test_render_without_span() {
+    let source = "var a = 1;\nprint -a;\nprint -nil;\n";
+    let error = LoxError::Runtime {
+        line: 3,
+        token: None,
+        span: None,
+        message: "Operand must be a number.".to_string(),
+    };
+
+    assert_eq!(diagnostic::render(&error, "test.lox", source), "\
+error[runtime]
+ --> test.lox:3
+  |
+3 | print -nil;
+  = Operand must be a number.
+");
+}
+
+#[test]
+fn test_render_without_position() {
+    let error = LoxError::Runtime {
+        line: 0,
+        token: None,
+        span: None,
+        message: "Could not write output: broken pipe.".to_string(),
+    };
+
+    let expected = "\
+error[runtime]
+ --> test.lox:0
+  |
+  = Could not write output: broken pipe.
+";
+    assert_eq!(diagnostic::render(&error, "test.lox", "print 1;\n"), expected);
+    assert_eq!(diagnostic::render(&error, "test.lox", ""), expected);
+}
+
+#[test]
+fn test_render_with_span_outside_source() {
+    let source = "print \"é\";\nprint -nil;\n";
+    let error = |start, end| LoxError::Runtime {
+        line: 2,
+        token: None,
+        span: Some(Span::new(start, end)),
+        message: "Operand must be a number.".to_string(),
+    };
+
+    let expected = "\
+error[runtime]
+ --> test.lox:2
+  |
+2 | print -nil;
+  = Operand must be a number.
+";
+    // Past the end, backwards, and inside the `é`.
+    for (start, end) in [(30, 34), (20, 18), (6, 8), (8, 10)] {
+        assert_eq!(diagnostic::render(&error(start, end), "test.lox", source), expected);
+    }
+}
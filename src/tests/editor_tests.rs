@@ -0,0 +1,67 @@
+#![cfg(test)]
+
+use crate::editor::Editor;
+
+/// Feeds `keys` to `editor` and returns the line it accepts.
+fn type_keys(editor: &mut Editor, keys: &str, names: &[&str]) -> Option<String> {
+    let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+    let mut output = Vec::new();
+    editor.edit(">>> ", &names, &mut keys.as_bytes(), &mut output).unwrap()
+}
+
+#[test]
+fn test_cursor_movement() {
+    let mut editor = Editor::new(None);
+    // Left five times, insert, Home, insert, End, backspace.
+    let line = type_keys(&mut editor, "prnt 1;\x1b[D\x1b[D\x1b[D\x1b[D\x1b[Di\x01>\x05\x7f\r", &[]);
+    assert_eq!(line, Some(">print 1".to_string()));
+    assert_eq!(type_keys(&mut editor, "", &[]), None);
+}
+
+#[test]
+fn test_history() {
+    let mut editor = Editor::new(None);
+    type_keys(&mut editor, "var a = 1;\r", &[]);
+    type_keys(&mut editor, "print a;\r", &[]);
+
+    assert_eq!(type_keys(&mut editor, "\x1b[A\x1b[A\r", &[]), Some("var a = 1;".to_string()));
+    assert_eq!(type_keys(&mut editor, "x\x1b[A\x1b[B\r", &[]), Some("x".to_string()));
+    // Ctrl-R finds the latest match; Ctrl-R again goes further back.
+    assert_eq!(type_keys(&mut editor, "\x12a\r", &[]), Some("var a = 1;".to_string()));
+    assert_eq!(type_keys(&mut editor, "\x12a;\x12\r", &[]), Some("print a;".to_string()));
+}
+
+#[test]
+fn test_history_file() {
+    let path = std::env::temp_dir().join(format!("rlox_history_{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut editor = Editor::new(Some(path.clone()));
+    type_keys(&mut editor, "print 1;\r", &[]);
+    type_keys(&mut editor, "print 2;\r", &[]);
+
+    let mut editor = Editor::new(Some(path.clone()));
+    assert_eq!(type_keys(&mut editor, "\x1b[A\x1b[A\r", &[]), Some("print 1;".to_string()));
+
+    // The file is kept to the newest 1000 lines, like the history itself.
+    let old: String = (0..1200).map(|i| format!("print {};\n", i)).collect();
+    std::fs::write(&path, old).unwrap();
+    let mut editor = Editor::new(Some(path.clone()));
+    type_keys(&mut editor, "print \"new\";\r", &[]);
+    let saved = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = saved.lines().collect();
+    assert_eq!(lines.len(), 1000);
+    assert_eq!(lines[0], "print 201;");
+    assert_eq!(lines[999], "print \"new\";");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_completion() {
+    let mut editor = Editor::new(None);
+    let names = ["print", "counter", "count_down", "clock"];
+
+    assert_eq!(type_keys(&mut editor, "pr\t 1;\r", &names), Some("print 1;".to_string()));
+    // Several matches only complete as far as they agree.
+    assert_eq!(type_keys(&mut editor, "f(cou\t\r", &names), Some("f(count".to_string()));
+}
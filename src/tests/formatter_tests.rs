@@ -0,0 +1,113 @@
+#![cfg(test)]
+
+use crate::{ast_json, formatter::format, parser::{Parser, Stmt}, scanner::Scanner};
+
+use super::parser_tests::without_positions;
+
+fn parse(source: &str) -> Vec<Stmt> {
+    let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap().clone();
+    Parser::new(tokens).parse().unwrap()
+}
+
+/// Formats `input`, checks it against `expected`, and checks that the
+/// result is stable and still means the same program.
+fn assert_formats(input: &str, expected: &str) {
+    let formatted = format(input).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format(&formatted).unwrap(), formatted, "formatting again changed the output");
+    assert_eq!(
+        without_positions(ast_json::program(&parse(&formatted))),
+        without_positions(ast_json::program(&parse(input))),
+    );
+}
+
+#[test]
+fn test_layout() {
+    assert_formats(
+        "var a=1;var b = -a ;fun f(x,y){if(x<y)return x;else{return !y;}}",
+        "\
+var a = 1;
+var b = -a;
+fun f(x, y) {
+    if (x < y) return x;
+    else {
+        return !y;
+    }
+}
+",
+    );
+    assert_formats(
+        "class A<B{init(x){this.x=x-1;super.init();}}\nfor(var i=0;i<3;i=i+1){print i;}\nwhile(true){}",
+        "\
+class A < B {
+    init(x) {
+        this.x = x - 1;
+        super.init();
+    }
+}
+for (var i = 0; i < 3; i = i + 1) {
+    print i;
+}
+while (true) {}
+",
+    );
+    assert_formats(
+        "var xs=[ 1,-2,[] ];xs[ 0 ]=f()[1]-xs [2];",
+        "var xs = [1, -2, []];\nxs[0] = f()[1] - xs[2];\n",
+    );
+    assert_formats(
+        "var m={ \"a\" :1,2:{} };if(m){print {1:a?b:c}[1];}\nf( { } );",
+        "var m = {\"a\": 1, 2: {}};\nif (m) {\n    print {1: a ? b : c}[1];\n}\nf({});\n",
+    );
+}
+
+#[test]
+fn test_doubled_semicolons_are_dropped() {
+    assert_formats(
+        "print 1;;\nwhile (true) { break;; }\nfun f() { return;; // done\n}",
+        "print 1;\nwhile (true) {\n    break;\n}\nfun f() {\n    return; // done\n}\n",
+    );
+}
+
+#[test]
+fn test_blank_lines() {
+    assert_formats(
+        "print 1;\n\n\n\nprint 2;\nfun f() {\n\n  print 3;\n\n  print 4;\n\n}\n",
+        "print 1;\n\nprint 2;\nfun f() {\n    print 3;\n\n    print 4;\n}\n",
+    );
+}
+
+#[test]
+fn test_comments_are_kept() {
+    assert_formats(
+        "\
+// leading
+var a = 1;   // trailing
+fun f() {
+// inside
+  return a * 2; /* a * b */
+}
+/* block
+   comment */
+print f();
+",
+        "\
+// leading
+var a = 1; // trailing
+fun f() {
+    // inside
+    return a * 2; /* a * b */
+}
+/* block
+   comment */
+print f();
+",
+    );
+}
+
+#[test]
+fn test_invalid_source_is_rejected() {
+    assert!(format("var x = ;").is_err());
+    assert!(format("print \"open").is_err());
+    assert_eq!(format("").unwrap(), "");
+}
@@ -1,31 +1,166 @@
 #![cfg(test)]
 
-use crate::{interpreter::Interpreter, parser::Parser, scanner::Scanner};
+use std::{cell::RefCell, io::Write, rc::Rc};
 
-#[test]
-fn test_print_statement() {
-    let input = "print 10 + 12".to_string();
-    let mut scanner = Scanner::new(input);
+use crate::{
+    compiler::Compiler,
+    diagnostic,
+    error::LoxError,
+    interpreter::Interpreter,
+    parser::{Parser, Stmt},
+    resolver::Resolver,
+    scanner::Scanner,
+    value::Value,
+    vm::Vm,
+};
+
+/// Collects everything the interpreter prints.
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Output {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+/// Runs `input` on both backends, checks that they agree, down to how a
+/// runtime error is rendered, and returns the result together with
+/// everything that was printed. A runtime error is reduced to its message.
+fn run(input: &str) -> (Result<(), String>, String) {
+    let statements = parse(input);
+
+    let output = Output::default();
+    let mut interpreter = Interpreter::with_output(Box::new(output.clone()));
+    let tree_walker = (interpreter.interpret(statements.clone()), output.text());
+
+    let output = Output::default();
+    let function = Compiler::new().compile(&statements).unwrap();
+    let vm = (Vm::with_output(Box::new(output.clone())).interpret(function), output.text());
+
+    let rendered = |(result, printed): &(Result<(), LoxError>, String)| {
+        (result.clone().map_err(|e| diagnostic::render(&e, "test.lox", input)), printed.clone())
+    };
+    assert_eq!(rendered(&tree_walker), rendered(&vm), "the backends disagree on:\n{}", input);
+
+    let (result, printed) = tree_walker;
+    (result.map_err(|e| e.message().to_string()), printed)
+}
+
+fn parse(input: &str) -> Vec<Stmt> {
+    let mut scanner = Scanner::new(input.to_string());
     let tokens = scanner.scan_tokens().unwrap();
     let mut parser = Parser::new(tokens.to_vec());
-    let statements = parser.parse().unwrap();
-    let mut interpreter = Interpreter::new();
-    let result = interpreter.interpret(statements);
+    let mut statements = parser.parse().unwrap();
+    Resolver::new().resolve(&mut statements).unwrap();
+    statements
+}
+
+#[test]
+fn test_print_statement() {
+    let input = "print 10 + 12";
+    let (result, _) = run(input);
 
-    assert_eq!(result, Some(()));
+    assert_eq!(result, Ok(()));
 }
 
 #[test]
 fn test_runtime_error() {
-    let input = "print -false".to_string();
-    let mut scanner = Scanner::new(input);
-    let tokens = scanner.scan_tokens().unwrap();
-    let mut parser = Parser::new(tokens.to_vec());
-    let statements = parser.parse().unwrap();
-    let mut interpreter = Interpreter::new();
-    let result = interpreter.interpret(statements);
+    let input = "print -false";
+    let (result, _) = run(input);
+
+    assert_eq!(result, Err("Operand must be a number.".to_string()));
+}
+
+#[test]
+fn test_runtime_errors_stop_every_statement_and_expression() {
+    let undefined = "Undefined Variable 'nope'.";
+    // One case per `Stmt` and `Expr` variant that evaluates something,
+    // with what it printed before failing.
+    let cases = [
+        // Statements.
+        ("nope;", "", undefined),
+        ("print nope;", "", undefined),
+        ("var a = nope;", "", undefined),
+        ("{ var a = 1; nope; }", "", undefined),
+        ("if (nope) print 1;", "", undefined),
+        ("if (true) nope; else print 1;", "", undefined),
+        ("if (false) print 1; else nope;", "", undefined),
+        ("while (nope) print 1;", "", undefined),
+        ("var i = 0; while (i < 3) { i = i + 1; if (i == 2) nope; print i; }", "1\n", undefined),
+        ("for (var i = 0; i < 3; i = i + nope) print i;", "0\n", undefined),
+        ("while (true) { if (nope) break; continue; }", "", undefined),
+        ("fun f() { print 1; nope; print 2; } f();", "1\n", undefined),
+        ("fun f() { return nope; } f();", "", undefined),
+        ("class A < nope {}", "", undefined),
+        // Expressions.
+        ("print nope + 1;", "", undefined),
+        ("print 1 + nope;", "", undefined),
+        ("print 1 + nil;", "", "Operands must be two numbers or two strings."),
+        ("print (nope);", "", undefined),
+        ("print -nope;", "", undefined),
+        ("print -\"s\";", "", "Operand must be a number."),
+        ("print nope ? 1 : 2;", "", undefined),
+        ("print true ? nope : 2;", "", undefined),
+        ("print false ? 1 : nope;", "", undefined),
+        ("var a; a = nope;", "", undefined),
+        ("nope = 1;", "", undefined),
+        ("print nope or true;", "", undefined),
+        ("print false or nope;", "", undefined),
+        ("nope();", "", undefined),
+        ("clock(nope);", "", undefined),
+        ("true();", "", "Can only call functions and classes."),
+        ("print nope.x;", "", undefined),
+        ("print true.x;", "", "Only instances have properties."),
+        ("nope.x = 1;", "", undefined),
+        ("class A {} A().x = nope;", "", undefined),
+        ("true.x = nope;", "", undefined),
+        ("fun f() { print \"value\"; } true.x = f();", "value\n", "Only instances have fields."),
+        ("class A { m() { return this.nope; } } A().m();", "", "Undefined property 'nope'."),
+        ("class A {} class B < A { m() { return super.nope; } } B().m();", "", "Undefined property 'nope'."),
+        ("print [1, nope];", "", undefined),
+        ("print nope[0];", "", undefined),
+        ("print [1][nope];", "", undefined),
+        ("print [1][1];", "", "List index 1 is out of bounds for a list of length 1."),
+        ("nope[0] = 1;", "", undefined),
+        ("[1][nope] = 1;", "", undefined),
+        ("[1][0] = nope;", "", undefined),
+        ("[1][-1] = 1;", "", "List index -1 is negative."),
+        ("print {nope: 1};", "", undefined),
+        ("print {1: nope};", "", undefined),
+        ("print {}[\"a\"];", "", "Key \"a\" isn't in the map."),
+        ("print {}[nope] = 1;", "", undefined),
+    ];
 
-    assert_eq!(result, None);
+    for (input, printed, message) in cases {
+        let input = format!("{} print \"unreachable\";", input);
+        assert_eq!(run(&input), (Err(message.to_string()), printed.to_string()), "for:\n{}", input);
+    }
+}
+
+#[test]
+fn test_deep_recursion_is_a_runtime_error() {
+    // Tests run on small stacks, so give this one what `main` gives scripts.
+    let input = "fun f(n) { return f(n + 1) + 1; } f(0); print \"unreachable\";";
+    let (result, printed) = std::thread::Builder::new()
+        .stack_size(crate::STACK_SIZE)
+        .spawn(move || run(input))
+        .unwrap()
+        .join()
+        .unwrap();
+
+    assert_eq!(result, Err("Stack overflow.".to_string()));
+    assert_eq!(printed, "");
 }
 
 #[test]
@@ -43,9 +178,403 @@ fn test_block_statements() {
     let mut scanner = Scanner::new(input);
     let tokens = scanner.scan_tokens().unwrap();
     let mut parser = Parser::new(tokens.to_vec());
-    let statements = parser.parse().unwrap();
+    let mut statements = parser.parse().unwrap();
+    Resolver::new().resolve(&mut statements).unwrap();
     let mut interpreter = Interpreter::new();
-    let result = interpreter.interpret(statements);
+    let error = interpreter.interpret(statements).unwrap_err();
+
+    assert!(matches!(error, LoxError::Runtime { line: 8, .. }));
+    assert_eq!(error.token().map(|t| t.lexeme.as_str()), Some("y"));
+    assert_eq!(error.message(), "Undefined Variable 'y'.");
+}
+
+#[test]
+fn test_string_operands_stay_strings() {
+    let input = "print \"12\" + \"3\"; print \"nil\" == nil; print -\"12\";";
+
+    assert_eq!(run(input), (Err("Operand must be a number.".to_string()), "123\nfalse\n".to_string()));
+}
+
+#[test]
+fn test_function_call() {
+    let input = "
+        fun fib(n) {
+            if (n < 2) return n;
+            return fib(n - 1) + fib(n - 2);
+        }
+        print fib(10);
+    ";
+    let (result, _) = run(input);
+
+    assert_eq!(result, Ok(()));
+}
+
+#[test]
+fn test_arity_mismatch() {
+    let input = "fun f(a, b) {} f(1);";
+    let (result, _) = run(input);
+
+    assert_eq!(result, Err("Expected 2 arguments but got 1.".to_string()));
+}
+
+#[test]
+fn test_closures_share_captured_variables() {
+    let input = "
+        fun make_counter() {
+            var i = 0;
+            fun count() { i = i + 1; return i; }
+            return count;
+        }
+        var counter = make_counter();
+        counter();
+        print counter();
+
+        var a = 1;
+        fun show() { print a; }
+        a = 5;
+        show();
+    ";
+
+    assert_eq!(run(input), (Ok(()), "2\n5\n".to_string()));
+}
+
+#[test]
+fn test_nested_block_assigns_outer_variable() {
+    let input = "
+        var x = 1;
+        { var y = 1; { x = x + 1; y = y + 1; } print y; }
+        print x;
+    ";
+
+    assert_eq!(run(input), (Ok(()), "2\n2\n".to_string()));
+}
+
+#[test]
+fn test_resolver_binds_closures_lexically() {
+    let input = "
+        var a = \"global\";
+        {
+            fun show() { print a; }
+            show();
+            var a = \"block\";
+            show();
+        }
+    ";
+
+    assert_eq!(run(input), (Ok(()), "global\nglobal\n".to_string()));
+}
+
+#[test]
+fn test_classes() {
+    let input = "
+        class Point {
+            init(x, y) { this.x = x; this.y = y; }
+            sum() { return this.x + this.y; }
+        }
+        var p = Point(1, 2);
+        var sum = p.sum;
+        p.x = 10;
+        print sum();
+        print p;
+        print p.init(0, 0) == p;
+    ";
+
+    assert_eq!(run(input), (Ok(()), "12\nPoint instance\ntrue\n".to_string()));
+}
+
+#[test]
+fn test_undefined_property() {
+    let (result, _) = run("class A {} A().missing;");
+
+    assert_eq!(result, Err("Undefined property 'missing'.".to_string()));
+}
+
+#[test]
+fn test_inheritance() {
+    let input = "
+        class A {
+            init(n) { this.n = n; }
+            who() { return \"A\"; }
+        }
+        class B < A {
+            init(n) { super.init(n * 2); }
+            who() { return \"B < \" + super.who(); }
+        }
+        class C < B {}
+        var c = C(2);
+        print c.who();
+        print c.n;
+    ";
+
+    assert_eq!(run(input), (Ok(()), "B < A\n4\n".to_string()));
+}
+
+#[test]
+fn test_superclass_must_be_a_class() {
+    let (result, _) = run("var NotAClass = 1; class A < NotAClass {}");
+
+    assert_eq!(result, Err("Superclass must be a class.".to_string()));
+}
+
+#[test]
+fn test_closures_capture_loop_and_block_variables() {
+    let input = "
+        var fns = nil;
+        {
+            var a = 1;
+            fun outer() {
+                var b = 2;
+                fun inner() { a = a + b; return a; }
+                return inner;
+            }
+            fns = outer();
+        }
+        print fns();
+        print fns();
+        for (var i = 0; i < 3; i = i + 1) {
+            var j = i * 2;
+            fun show() { print j; }
+            show();
+        }
+    ";
+
+    assert_eq!(run(input), (Ok(()), "3\n5\n0\n2\n4\n".to_string()));
+}
+
+#[test]
+fn test_break_and_continue() {
+    let input = "
+        for (var i = 0; i < 10; i = i + 1) {
+            if (i == 1) continue;
+            var square = i * i;
+            if (square > 10) break;
+            print square;
+        }
+        var n = 0;
+        var last = nil;
+        while (true) {
+            n = n + 1;
+            var k = n;
+            fun get() { return k; }
+            if (n < 3) { last = get; continue; }
+            break;
+        }
+        print last();
+        for (var a = 0; a < 3; a = a + 1) {
+            for (var b = 0; b < 3; b = b + 1) {
+                if (b == 1) continue;
+                if (a == 1) break;
+                print a * 10 + b;
+            }
+        }
+    ";
+
+    assert_eq!(run(input), (Ok(()), "0
+4
+9
+2
+0
+2
+20
+22
+".to_string()));
+
+    // A `var` initializer with no condition after it.
+    let input = "for (var i = 0;; i = i + 1) { if (i > 2) break; print i; }";
+    assert_eq!(run(input), (Ok(()), "0\n1\n2\n".to_string()));
+}
+
+#[test]
+fn test_lists() {
+    let input = "
+        var xs = [1, 2, [3]];
+        xs[1] = \"two\";
+        xs[2][0] = xs[0] + 10;
+        print xs;
+        var ys = xs;
+        push(ys, nil);
+        print len(xs);
+        print pop(xs);
+        insert(xs, 0, 0);
+        insert(xs, len(xs), \"end\");
+        print remove(xs, 1);
+        print xs;
+        print len(\"héllo\");
+        print [] == [];
+        push(xs, xs);
+        print xs;
+    ";
+
+    assert_eq!(run(input), (Ok(()), concat!(
+        "[1, \"two\", [11]]\n4\nnil\n1\n",
+        "[0, \"two\", [11], \"end\"]\n5\nfalse\n",
+        "[0, \"two\", [11], \"end\", [...]]\n",
+    ).to_string()));
+}
+
+#[test]
+fn test_list_errors() {
+    for (input, message) in [
+        ("print [1][-1];", "List index -1 is negative."),
+        ("print [1][0.5];", "List index 0.5 isn't a whole number."),
+        ("print [1][\"0\"];", "List index must be a number."),
+        ("print nil[0];", "Only lists and maps can be indexed."),
+        ("var xs = [1]; xs[0] = pop(xs);", "List index 0 is out of bounds for a list of length 0."),
+        ("pop([]);", "Can't pop from an empty list."),
+        ("push(\"s\", 1);", "The first argument to 'push' must be a list."),
+        ("insert([], 1, 0);", "List index 1 is out of bounds for a list of length 0."),
+        ("remove([1], 1);", "List index 1 is out of bounds for a list of length 1."),
+        ("len(1);", "Can only take the length of a list, a map or a string."),
+    ] {
+        assert_eq!(run(input).0, Err(message.to_string()), "for: {}", input);
+    }
+}
+
+#[test]
+fn test_maps() {
+    let input = "
+        var m = {\"b\": 1, \"a\": [2], 3: nil};
+        m[\"a\"][0] = m[\"b\"] + 10;
+        m[\"b\"] = \"one\";
+        m[0] = {};
+        print m;
+        print m[3.0];
+        print len(m);
+        print keys(m);
+        print values({1: 2, -0: 3});
+        print has(m, \"a\");
+        print has(m, \"c\");
+        print delete(m, \"b\");
+        print delete(m, \"b\");
+        m[\"b\"] = true;
+        print keys(m);
+        print {} == {};
+        m[\"self\"] = m;
+        print m[\"self\"][\"self\"][\"a\"];
+        print m;
+    ";
+
+    assert_eq!(run(input), (Ok(()), concat!(
+        "{\"b\": \"one\", \"a\": [11], 3: nil, 0: {}}\nnil\n4\n[\"b\", \"a\", 3, 0]\n[2, 3]\n",
+        "true\nfalse\ntrue\nfalse\n[\"a\", 3, 0, \"b\"]\nfalse\n[11]\n",
+        "{\"a\": [11], 3: nil, 0: {}, \"b\": true, \"self\": {...}}\n",
+    ).to_string()));
+}
+
+#[test]
+fn test_map_errors() {
+    for (input, message) in [
+        ("print {\"a\": 1}[\"b\"];", "Key \"b\" isn't in the map."),
+        ("print {1: 1}[2];", "Key 2 isn't in the map."),
+        ("print {nil: 1};", "Map keys must be strings or numbers."),
+        ("var m = {}; m[[]] = 1;", "Map keys must be strings or numbers."),
+        ("print {}[0 / 0];", "A map key can't be NaN."),
+        ("has({}, true);", "Map keys must be strings or numbers."),
+        ("keys([]);", "The first argument to 'keys' must be a map."),
+        ("delete(nil, 1);", "The first argument to 'delete' must be a map."),
+    ] {
+        assert_eq!(run(input).0, Err(message.to_string()), "for: {}", input);
+    }
+
+    // Every entry runs before a bad key is reported.
+    let input = "fun f(x) { print x; return x; } print {nil: f(1), f(2): f(3)};";
+    assert_eq!(run(input), (Err("Map keys must be strings or numbers.".to_string()), "1\n2\n3\n".to_string()));
+}
+
+#[test]
+fn test_ternary_logical_and_comparisons() {
+    let input = "
+        print 1 < 2 ? \"yes\" : \"no\";
+        print nil or \"default\";
+        print false and 1;
+        print 2 >= 2;
+        print 3 <= 2;
+        print 1 != 2;
+    ";
+
+    assert_eq!(run(input), (Ok(()), "yes\ndefault\nfalse\ntrue\nfalse\ntrue\n".to_string()));
+}
+
+#[test]
+fn test_local_classes() {
+    let input = "
+        fun make() {
+            class Base { name() { return \"base\"; } }
+            class Node < Base {
+                init(v) { this.v = v; }
+                copy() { return Node(this.v + 1); }
+                name() { return \"node of \" + super.name(); }
+            }
+            return Node(1).copy();
+        }
+        var n = make();
+        print n.v;
+        print n.name();
+    ";
+
+    assert_eq!(run(input), (Ok(()), "2\nnode of base\n".to_string()));
+}
+
+#[test]
+fn test_state_persists_between_runs() {
+    let first = "var count = 1; fun bump() { count = count + 1; }";
+    let second = "bump(); bump();";
+
+    let mut interpreter = Interpreter::with_output(Box::new(Output::default()));
+    interpreter.interpret(parse(first)).unwrap();
+    interpreter.interpret(parse(second)).unwrap();
+
+    let mut vm = Vm::with_output(Box::new(Output::default()));
+    vm.interpret(Compiler::new().compile(&parse(first)).unwrap()).unwrap();
+    vm.interpret(Compiler::new().compile(&parse(second)).unwrap()).unwrap();
+
+    let globals = interpreter.globals();
+    assert_eq!(globals[1], ("count".to_string(), Value::Number(3.0)));
+    assert_eq!(globals.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["bump", "count"]);
+    assert_eq!(vm.globals()[1], globals[1]);
+}
+
+#[test]
+fn test_evaluate_lone_expressions() {
+    let setup = parse("var count = 1; fun bump() { count = count + 1; }");
+    let mut interpreter = Interpreter::with_output(Box::new(Output::default()));
+    interpreter.interpret(setup.clone()).unwrap();
+    let mut vm = Vm::with_output(Box::new(Output::default()));
+    vm.interpret(Compiler::new().compile(&setup).unwrap()).unwrap();
+
+    let cases = [
+        ("bump();", Ok(Value::Nil)),
+        ("count * 10;", Ok(Value::Number(20.0))),
+        ("\"a\" + \"b\";", Ok(Value::String("ab".to_string()))),
+        ("nope;", Err("Undefined Variable 'nope'.".to_string())),
+    ];
+    for (input, expected) in cases {
+        let Some(Stmt::Expression(expr)) = parse(input).pop() else { panic!("not an expression: {}", input) };
+        let tree_walker = interpreter.evaluate(&expr).map_err(|e| e.message().to_string());
+        let function = Compiler::new().compile_expression(&expr).unwrap();
+        let from_vm = vm.evaluate(function).map_err(|e| e.message().to_string());
+        assert_eq!(tree_walker, expected, "for {}", input);
+        assert_eq!(from_vm, expected, "for {}", input);
+    }
+}
+
+#[test]
+fn test_globals_from_the_host() {
+    let statements = parse("print args; print env(\"RLOX_TEST_VARIABLE\"); print env(\"RLOX_UNSET_VARIABLE\");");
+    std::env::set_var("RLOX_TEST_VARIABLE", "set");
+    let args = || Value::list(vec![Value::String("a, b".to_string()), Value::Number(1.0)]);
+
+    let output = Output::default();
+    let mut interpreter = Interpreter::with_output(Box::new(output.clone()));
+    interpreter.define_global("args", args());
+    interpreter.interpret(statements.clone()).unwrap();
+    assert_eq!(output.text(), "[\"a, b\", 1]\nset\nnil\n");
+
+    let output = Output::default();
+    let mut vm = Vm::with_output(Box::new(output.clone()));
+    vm.define_global("args", args());
+    vm.interpret(Compiler::new().compile(&statements).unwrap()).unwrap();
+    assert_eq!(output.text(), "[\"a, b\", 1]\nset\nnil\n");
 
-    assert_eq!(result, None);
+    assert_eq!(run("exit(1.5);").0, Err("Exit code must be an integer between 0 and 255.".to_string()));
 }
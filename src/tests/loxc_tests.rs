@@ -0,0 +1,115 @@
+#![cfg(test)]
+
+use std::rc::Rc;
+
+use crate::{
+    chunk::{Chunk, Constant, FunctionProto, OpCode},
+    compiler::Compiler,
+    loxc,
+    parser::Parser,
+    resolver::Resolver,
+    scanner::Scanner,
+    vm::Vm,
+};
+
+fn compile(input: &str) -> Rc<FunctionProto> {
+    let mut scanner = Scanner::new(input.to_string());
+    let tokens = scanner.scan_tokens().unwrap();
+    let mut parser = Parser::new(tokens.to_vec());
+    let mut statements = parser.parse().unwrap();
+    Resolver::new().resolve(&mut statements).unwrap();
+    Compiler::new().compile(&statements).unwrap()
+}
+
+#[test]
+fn test_round_trip() {
+    let input = "
+        class Counter {
+            init(start) { this.count = start; }
+            next() { this.count = this.count + 1; return this.count; }
+        }
+        fun make() {
+            var counter = Counter(1.5);
+            fun step() { return counter.next(); }
+            return step;
+        }
+        var step = make();
+        step();
+        print \"count: \" + step();
+    ";
+    let function = compile(input);
+
+    let bytes = loxc::serialize(&function);
+    assert!(loxc::is_loxc(&bytes));
+
+    let loaded = loxc::deserialize(&bytes).unwrap();
+    // Spans aren't saved, so compare everything else by writing it out again.
+    assert!(loaded.chunk.spans.is_empty());
+    assert_eq!(loxc::serialize(&loaded), bytes);
+    assert!(Vm::new().interpret(Rc::new(loaded)).is_ok());
+}
+
+#[test]
+fn test_rejects_malformed_files() {
+    let bytes = loxc::serialize(&compile("print 1;"));
+
+    assert!(loxc::deserialize(b"print 1;").is_err());
+
+    let mut wrong_version = bytes.clone();
+    wrong_version[4] = 99;
+    assert!(loxc::deserialize(&wrong_version).is_err());
+
+    assert!(loxc::deserialize(&bytes[..bytes.len() - 1]).is_err());
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(loxc::deserialize(&trailing).is_err());
+}
+
+/// A `.loxc` file whose script is `code`, as a hand-made file might be.
+fn script(code: &[u8], constants: Vec<Constant>) -> Vec<u8> {
+    let chunk = Chunk { code: code.to_vec(), constants, lines: vec![1; code.len()], spans: Vec::new() };
+    loxc::serialize(&FunctionProto { name: "script".to_string(), arity: 0, upvalue_count: 0, chunk })
+}
+
+#[test]
+fn test_rejects_invalid_bytecode() {
+    let (nil, ret, pop) = (OpCode::Nil as u8, OpCode::Return as u8, OpCode::Pop as u8);
+    let number = || vec![Constant::Number(1.0)];
+
+    assert!(loxc::deserialize(&script(&[nil, ret], Vec::new())).is_ok());
+
+    let mut with_parameter = compile("print 1;");
+    Rc::get_mut(&mut with_parameter).unwrap().arity = 1;
+    assert!(loxc::deserialize(&loxc::serialize(&with_parameter)).is_err());
+    for (code, constants) in [
+        // Operands missing at the end of the code.
+        (vec![OpCode::Constant as u8], Vec::new()),
+        (vec![nil, OpCode::Jump as u8, 0], Vec::new()),
+        // A constant that isn't in the pool, or is the wrong kind.
+        (vec![OpCode::Constant as u8, 0, 1, ret], number()),
+        (vec![OpCode::GetGlobal as u8, 0, 0, ret], number()),
+        // A local slot above the stack, and an upvalue the script doesn't have.
+        (vec![OpCode::GetLocal as u8, 200, ret], Vec::new()),
+        (vec![OpCode::GetUpvalue as u8, 0, ret], Vec::new()),
+        // Popping more than was pushed.
+        (vec![pop, pop, nil, ret], Vec::new()),
+        // Jumping into an operand, before the code, or off its end.
+        (vec![OpCode::Jump as u8, 0, 1, OpCode::Constant as u8, 0, 0, ret], number()),
+        (vec![OpCode::Loop as u8, 0, 9, nil, ret], Vec::new()),
+        (vec![nil], Vec::new()),
+        // Reaching one instruction with two different stack heights.
+        (vec![nil, OpCode::JumpIfFalse as u8, 0, 1, nil, ret], Vec::new()),
+        (vec![99], Vec::new()),
+    ] {
+        assert!(loxc::deserialize(&script(&code, constants)).is_err(), "accepted {:?}", code);
+    }
+}
+
+#[test]
+fn test_zero_and_negative_zero_are_separate_constants() {
+    let mut chunk = Chunk::default();
+    assert_eq!(chunk.add_constant(Constant::Number(0.0)), 0);
+    assert_eq!(chunk.add_constant(Constant::Number(-0.0)), 1);
+    assert_eq!(chunk.add_constant(Constant::Number(0.0)), 0);
+}
@@ -1,3 +1,12 @@
+mod ast_json_tests;
+mod cli_tests;
+mod diagnostic_tests;
+mod editor_tests;
+mod formatter_tests;
 mod interpreter_tests;
+mod loxc_tests;
 mod parser_tests;
+mod repl_tests;
+mod resolver_tests;
 mod scanner_tests;
+mod value_tests;
@@ -1,6 +1,9 @@
 #![cfg(test)]
 
-use crate::{parser::{Expr, Parser, Stmt}, scanner::Scanner, token::{Token, TokenType}};
+use crate::{
+    ast_json, json::Json, parser::{Expr, FunctionDecl, Parser, Stmt, MAX_NESTING}, scanner::Scanner,
+    token::{Span, Token, TokenType}, value::Value,
+};
 
 #[test]
 fn test_binary_expr() {
@@ -10,9 +13,9 @@ fn test_binary_expr() {
         Stmt::Expression(
             Box::new(
                 Expr::Binary(
-                    Box::new(Expr::Literal("10".to_string())),
-                    Token::new(TokenType::Plus, "+".to_string(), 1),
-                    Box::new(Expr::Literal("12".to_string()))
+                    Box::new(Expr::Literal(Value::Number(10.0), Span::new(0, 2))),
+                    token(TokenType::Plus, "+", 4),
+                    Box::new(Expr::Literal(Value::Number(12.0), Span::new(5, 7)))
                     )
                 )
             )
@@ -26,7 +29,7 @@ fn test_literal_expr() {
     let input = "10".to_string();
     let mut parser = get_parser(input);
     let expected: Vec<Stmt> = vec![
-        Stmt::Expression(Box::new(Expr::Literal("10".to_string())))
+        Stmt::Expression(Box::new(Expr::Literal(Value::Number(10.0), Span::new(0, 2))))
     ];
 
     assert_eq!(parser.parse().unwrap(), expected);
@@ -37,7 +40,10 @@ fn test_grouping_expr() {
     let input = "(10)".to_string();
     let mut parser = get_parser(input);
     let expected: Vec<Stmt> = vec![
-        Stmt::Expression(Box::new(Expr::Grouping(Box::new(Expr::Literal("10".to_string())))))
+        Stmt::Expression(Box::new(Expr::Grouping(
+            Box::new(Expr::Literal(Value::Number(10.0), Span::new(1, 3))),
+            Span::new(0, 4)
+        )))
     ];
 
     assert_eq!(parser.parse().unwrap(), expected);
@@ -51,8 +57,8 @@ fn test_unary_expr() {
         Stmt::Expression(
             Box::new(
                 Expr::Unary(
-                    Token::new(TokenType::Bang, "!".to_string(), 1),
-                    Box::new(Expr::Literal("true".to_string()))
+                    token(TokenType::Bang, "!", 1),
+                    Box::new(Expr::Literal(Value::Bool(true), Span::new(1, 5)))
                     )
                 )
             )
@@ -79,9 +85,9 @@ fn test_ternary() {
     let expected: Vec<Stmt> = vec![
         Stmt::Expression(
             Box::new(Expr::Ternary(
-                Box::new(Expr::Literal("true".to_string())),
-                Box::new(Expr::Literal("1".to_string())),
-                Box::new(Expr::Literal("2".to_string())),
+                Box::new(Expr::Literal(Value::Bool(true), Span::new(0, 4))),
+                Box::new(Expr::Literal(Value::Number(1.0), Span::new(7, 8))),
+                Box::new(Expr::Literal(Value::Number(2.0), Span::new(11, 12))),
             ))
         )
     ];
@@ -89,12 +95,288 @@ fn test_ternary() {
     assert_eq!(parser.parse().unwrap(), expected);
 }
 
+#[test]
+fn test_call_expr() {
+    let input = "add(1, 2)".to_string();
+    let mut parser = get_parser(input);
+
+    let expected: Vec<Stmt> = vec![
+        Stmt::Expression(
+            Box::new(Expr::Call(
+                Box::new(Expr::Variable(token(TokenType::Identifier, "add", 1), None)),
+                token(TokenType::RightParen, ")", 9),
+                vec![
+                    Expr::Literal(Value::Number(1.0), Span::new(4, 5)),
+                    Expr::Literal(Value::Number(2.0), Span::new(7, 8)),
+                ],
+            ))
+        )
+    ];
+
+    assert_eq!(parser.parse().unwrap(), expected);
+}
+
+#[test]
+fn test_function_declaration() {
+    let input = "fun id(x) { return x; }".to_string();
+    let mut parser = get_parser(input);
+
+    let expected: Vec<Stmt> = vec![
+        Stmt::Function(FunctionDecl {
+            name: token(TokenType::Identifier, "id", 5),
+            params: vec![token(TokenType::Identifier, "x", 8)],
+            body: vec![Stmt::Return(
+                token(TokenType::Return, "return", 13),
+                Some(Box::new(Expr::Variable(token(TokenType::Identifier, "x", 20), None))),
+            )],
+        })
+    ];
+
+    assert_eq!(parser.parse().unwrap(), expected);
+}
+
+#[test]
+fn test_parse_errors_are_collected() {
+    let mut parser = get_parser("var = 1; print 2; print (3;".to_string());
+    let errors = parser.parse().unwrap_err();
+
+    let found: Vec<(&str, &str)> = errors.iter()
+        .map(|e| (e.token().unwrap().lexeme.as_str(), e.message()))
+        .collect();
+    assert_eq!(found, vec![
+        ("=", "Expect identifier after 'var'"),
+        (";", "Expect ')' after expression."),
+    ]);
+}
+
+#[test]
+fn test_loop_jumps_outside_loops() {
+    for (input, keyword) in [
+        ("break;", "break"),
+        ("if (a) continue;", "continue"),
+        ("while (a) { fun f() { break; } }", "break"),
+    ] {
+        let errors = get_parser(input.to_string()).parse().unwrap_err();
+        assert_eq!(errors[0].message(), format!("Can't use '{}' outside of a loop.", keyword));
+    }
+
+    assert!(get_parser("while (a) { { if (b) break; } } for (;;) continue;".to_string()).parse().is_ok());
+    assert!(get_parser("fun f() { while (a) { fun g() {} break; } }".to_string()).parse().is_ok());
+}
+
+#[test]
+fn test_nesting_limit() {
+    // Parsing near the limit takes more stack than tests get, so give it
+    // what `main` gives scripts.
+    std::thread::Builder::new().stack_size(crate::STACK_SIZE).spawn(|| {
+        let n = MAX_NESTING + 1;
+        let too_deep = [
+            format!("print {}1{};", "(".repeat(n), ")".repeat(n)),
+            format!("print {}1;", "-".repeat(n)),
+            format!("print 1{};", "+1".repeat(n)),
+            format!("f{};", "()".repeat(n)),
+            format!("a{} = 1;", ".b".repeat(n)),
+            format!("a = {}1;", "a = ".repeat(n)),
+            format!("print {}1;", "a ? 1 : ".repeat(n)),
+            format!("{}{}", "{".repeat(n), "}".repeat(n)),
+            format!("{}print 1;", "if (true) ".repeat(n)),
+            format!("{}{}", "fun f() {".repeat(n), "}".repeat(n)),
+            // Chains inside groupings add up.
+            format!("print (1{}) + 1{};", "+1".repeat(n / 2), "+1".repeat(n / 2)),
+        ];
+        for input in too_deep {
+            let errors = get_parser(input.clone()).parse().unwrap_err();
+            assert_eq!(errors[0].message(), "Too much nesting.", "for {}", input);
+        }
+
+        let deep = MAX_NESTING - 8;
+        assert!(get_parser(format!("print {}1{};", "(".repeat(deep), ")".repeat(deep))).parse().is_ok());
+        assert!(get_parser(format!("print 1{};", "+1".repeat(deep))).parse().is_ok());
+        assert!(get_parser(format!("{}{}", "{".repeat(deep), "}".repeat(deep))).parse().is_ok());
+    }).unwrap().join().unwrap();
+}
+
+/// A token on the first line, starting at the 1-based `column`.
+fn token(token_type: TokenType, lexeme: &str, column: usize) -> Token {
+    let span = Span::new(column - 1, column - 1 + lexeme.len());
+    Token::new(token_type, lexeme.to_string(), 1).at(column, span)
+}
+
 fn get_parser(input: String) -> Parser {
     let mut scanner = Scanner::new(input);
-    let tokens = scanner.scan_tokens();
-    if let None = tokens {
-        panic!("tokens are none.");
+    let tokens = scanner.scan_tokens().expect("scanning failed.");
+
+    Parser::new(tokens.to_vec())
+}
+
+/// The tree as JSON with spans, lines and columns left out.
+pub(super) fn without_positions(json: Json) -> Json {
+    match json {
+        Json::Object(fields) => Json::Object(
+            fields.into_iter()
+                .filter(|(key, _)| !matches!(key.as_str(), "span" | "line" | "column"))
+                .map(|(key, value)| (key, without_positions(value)))
+                .collect()
+        ),
+        Json::Array(items) => Json::Array(items.into_iter().map(without_positions).collect()),
+        json => json,
+    }
+}
+
+/// Parses `input`, prints it, and checks that parsing the printed source
+/// gives the same tree apart from positions.
+fn assert_round_trips(input: &str) {
+    let statements = get_parser(input.to_string()).parse().unwrap();
+    let printed: String = statements.iter().map(|stmt| format!("{}\n", stmt)).collect();
+    let reparsed = get_parser(printed.clone()).parse()
+        .unwrap_or_else(|errors| panic!("printed source doesn't parse: {:?}\n{}", errors, printed));
+
+    assert_eq!(
+        without_positions(ast_json::program(&reparsed)),
+        without_positions(ast_json::program(&statements)),
+        "\ninput:\n{}\nprinted:\n{}", input, printed
+    );
+}
+
+#[test]
+fn test_display_round_trips() {
+    for input in [
+        "10 + 12", "(10 + 12) / 2", "!true", "-(-x)", "true ? 1 : 2", "add(1, 2)(3)",
+        "a = b = c;", "x.y.z = f().w;", "a or b and !c == d;", "print \"it's\"; print 'say \"hi\"';",
+        "print `tick`; print `a\"b'c`;",
+        "var a; var b = nil;", "{} { var x = 1; { print x; } }",
+        "if (a) if (b) print 1; else print 2;", "if (a) { print 1; } else if (b) print 2;",
+        "while (i < 10) i = i + 1;", "for (;;) print 1;", "for (var i = 0; i < 3; i = i + 1) { print i; }",
+        "while (a) { if (b) break; continue; }", "for (; i < 3; i = i + 1) if (i) continue;",
+        "fun f() {} fun g(a, b) { return; return a + b; }",
+        "class A {} class B < A { init(x) { this.x = x; } m() { return super.m(); } }",
+        "print \"two\nlines\";", "{ print \"  indented\n    string\"; }",
+        "[]; [1, [2, 3]]; xs[0]; xs[i + 1][0] = [];", "f()[0](1)[2]; a.b[0].c = -xs[1];",
+        "var m = {}; print {\"a\": 1, 2: {b: c ? d : e}}; m[\"k\"] = f({1: 2})[1];",
+    ] {
+        assert_round_trips(input);
+    }
+}
+
+/// A small xorshift generator, so the property test needs no dependencies
+/// and fails the same way every run.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
     }
 
-    Parser::new(tokens.unwrap().to_vec())
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len() as u64) as usize]
+    }
+}
+
+fn random_primary(rng: &mut Rng, depth: u32) -> String {
+    match rng.below(if depth == 0 { 4 } else { 9 }) {
+        0 => rng.below(1000).to_string(),
+        1 => format!("{}.{}", rng.below(100), rng.below(100) + 1),
+        2 => rng.pick(&["a", "b", "counter", "true", "false", "nil", "this", "super.m"]).to_string(),
+        3 => rng.pick(&["\"\"", "\"text\"", "'it''s'", "\"a\nb\"", "'say \"hi\"'", "`tick`", "`it's \"so\"`"])
+            .replace("''", "\""),
+        4 => format!("({})", random_expr(rng, depth - 1)),
+        5 => {
+            let args: Vec<String> = (0..rng.below(3)).map(|_| random_expr(rng, depth - 1)).collect();
+            format!("{}({})", rng.pick(&["f", "a.b", "g(1)"]), args.join(", "))
+        },
+        6 => {
+            let items: Vec<String> = (0..rng.below(3)).map(|_| random_expr(rng, depth - 1)).collect();
+            format!("[{}]", items.join(", "))
+        },
+        7 => {
+            let entries: Vec<String> = (0..rng.below(3))
+                .map(|_| format!("{}: {}", random_expr(rng, depth - 1), random_expr(rng, depth - 1)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        },
+        _ => format!("{}[{}]", random_primary(rng, depth - 1), random_expr(rng, depth - 1)),
+    }
+}
+
+fn random_expr(rng: &mut Rng, depth: u32) -> String {
+    if depth == 0 {
+        return random_primary(rng, 0);
+    }
+
+    match rng.below(7) {
+        0 | 1 => {
+            let op = rng.pick(&["+", "-", "*", "/", "==", "!=", "<", "<=", ">", ">=", "and", "or"]);
+            format!("{} {} {}", random_expr(rng, depth - 1), op, random_expr(rng, depth - 1))
+        },
+        2 => format!("{}{}", rng.pick(&["-", "!"]), random_primary(rng, depth - 1)),
+        // The parser only takes a ternary where it won't run into an operator.
+        3 => format!(
+            "({} ? {} : {})",
+            random_primary(rng, depth - 1), random_primary(rng, 0), random_primary(rng, 0)
+        ),
+        // An assignment inside another expression needs parentheses.
+        4 => format!("({} = {})", rng.pick(&["a", "x.y", "f().z", "xs[0]", "m[a][b]"]), random_expr(rng, depth - 1)),
+        5 => format!("{}.{}", random_primary(rng, depth - 1), rng.pick(&["x", "len"])),
+        _ => random_primary(rng, depth),
+    }
+}
+
+/// A random statement; `declarations` allows `var`, `fun` and `class`,
+/// which can't be the body of an `if` or a loop, and `in_loop` allows
+/// `break` and `continue`.
+fn random_stmt(rng: &mut Rng, depth: u32, declarations: bool, in_loop: bool) -> String {
+    let expr = |rng: &mut Rng| random_expr(rng, 2);
+    loop {
+        let stmt = match rng.below(if depth == 0 { 5 } else { 11 }) {
+            0 => format!("print {};", expr(rng)),
+            1 => match expr(rng) {
+                // A `{` that starts a statement opens a block, not a map.
+                expr if expr.starts_with('{') => continue,
+                expr => format!("{};", expr),
+            },
+            2 if !declarations => format!("{} = {};", rng.pick(&["a", "x.y"]), expr(rng)),
+            2 if declarations => format!("var {} = {};", rng.pick(&["a", "b"]), expr(rng)),
+            3 => "return;".to_string(),
+            4 if depth == 0 && in_loop => rng.pick(&["break;", "continue;"]).to_string(),
+            4 if depth == 0 => continue,
+            4 => {
+                let body: Vec<String> = (0..rng.below(3)).map(|_| random_stmt(rng, depth - 1, true, in_loop)).collect();
+                format!("{{ {} }}", body.join(" "))
+            },
+            5 => format!("if ({}) {}", expr(rng), random_stmt(rng, depth - 1, false, in_loop)),
+            6 => format!(
+                "if ({}) {} else {}",
+                expr(rng), random_stmt(rng, depth - 1, false, in_loop), random_stmt(rng, depth - 1, false, in_loop)
+            ),
+            7 => format!("while ({}) {}", expr(rng), random_stmt(rng, depth - 1, false, true)),
+            8 => {
+                let init = rng.pick(&["", "i = 0", "var i = 0"]);
+                let cond = rng.pick(&["", "i < 10"]);
+                let step = rng.pick(&["", "i = i + 1"]);
+                format!("for ({}; {}; {}) {}", init, cond, step, random_stmt(rng, depth - 1, false, true))
+            },
+            9 if declarations => format!(
+                "fun {}({}) {{ {} return {}; }}",
+                rng.pick(&["f", "g"]), rng.pick(&["", "x", "x, y"]), random_stmt(rng, depth - 1, true, false), expr(rng)
+            ),
+            10 if declarations => format!(
+                "class C{} {{ m() {{ {} }} }}",
+                rng.pick(&["", " < B"]), random_stmt(rng, depth - 1, true, false)
+            ),
+            _ => continue,
+        };
+        return stmt;
+    }
+}
+
+#[test]
+fn test_display_round_trips_random_programs() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..500 {
+        let program: Vec<String> = (0..1 + rng.below(4)).map(|_| random_stmt(&mut rng, 3, true, false)).collect();
+        assert_round_trips(&program.join("\n"));
+    }
 }
@@ -0,0 +1,47 @@
+#![cfg(test)]
+
+use crate::{compiler::Compiler, repl::is_incomplete, Backend, Lox};
+
+#[test]
+fn test_complete_input() {
+    assert!(!is_incomplete("print 1;\n"));
+    assert!(!is_incomplete("while (true) { print 1; }\n"));
+    // Errors that more input can't fix are left for `run` to report.
+    assert!(!is_incomplete("print );\n"));
+    assert!(!is_incomplete("var x = @;\n"));
+}
+
+#[test]
+fn test_incomplete_input() {
+    assert!(is_incomplete("while (i < 3) {\n"));
+    assert!(is_incomplete("print add(1,\n"));
+    assert!(is_incomplete("var xs = [1,\n"));
+    assert!(is_incomplete("print \"first line\n"));
+    assert!(is_incomplete("var x =\n"));
+    assert!(is_incomplete("fun f()\n"));
+}
+
+#[test]
+fn test_error_in_function_from_earlier_input() {
+    for backend in [Backend::Interpreter, Backend::Vm] {
+        let mut lox = Lox::new();
+        lox.backend = backend;
+        lox.file_name = "lib.lox".to_string();
+        lox.run("fun f() {\n  return -nil;\n}\n".to_string(), false);
+
+        lox.file_name = "<repl>".to_string();
+        let statements = lox.analyze("print \"aééééééé\" + f();\n".to_string()).unwrap();
+        let result = match backend {
+            Backend::Interpreter => lox.interpreter.interpret(statements),
+            Backend::Vm => lox.vm.interpret(Compiler::new().compile(&statements).unwrap()),
+        };
+
+        assert_eq!(lox.render(&result.unwrap_err()), "\
+error[runtime]
+ --> lib.lox:2:10
+  |
+2 |   return -nil;
+  |          ^^^^ Operand must be a number.
+");
+    }
+}
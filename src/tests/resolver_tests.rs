@@ -0,0 +1,63 @@
+#![cfg(test)]
+
+use crate::{parser::{Expr, Parser, Stmt}, resolver::Resolver, scanner::Scanner};
+
+#[test]
+fn test_local_depths() {
+    let mut statements = resolve("var a = 1; { var b = 2; { print a + b; } }").unwrap();
+
+    let Stmt::Block(outer, _) = &mut statements[1] else { panic!("expected a block.") };
+    let Stmt::Block(inner, _) = &mut outer[1] else { panic!("expected a block.") };
+    let Stmt::Print(expr, _) = &inner[0] else { panic!("expected print.") };
+    let Expr::Binary(a, _, b) = expr.as_ref() else { panic!("expected a binary expr.") };
+
+    assert!(matches!(a.as_ref(), Expr::Variable(_, None)));
+    assert!(matches!(b.as_ref(), Expr::Variable(_, Some(1))));
+}
+
+#[test]
+fn test_read_in_own_initializer() {
+    assert!(resolve("{ var a = a; }").is_none());
+    assert!(resolve("var a = 1; var a = a;").is_some());
+}
+
+#[test]
+fn test_redeclare_in_same_scope() {
+    assert!(resolve("{ var a = 1; var a = 2; }").is_none());
+    assert!(resolve("{ var a = 1; { var a = 2; } }").is_some());
+}
+
+#[test]
+fn test_top_level_return() {
+    assert!(resolve("return 1;").is_none());
+    assert!(resolve("fun f() { return 1; }").is_some());
+}
+
+#[test]
+fn test_this_outside_class() {
+    assert!(resolve("print this;").is_none());
+    assert!(resolve("fun f() { return this; }").is_none());
+    assert!(resolve("class A { m() { return this; } }").is_some());
+}
+
+#[test]
+fn test_return_value_from_initializer() {
+    assert!(resolve("class A { init() { return 1; } }").is_none());
+    assert!(resolve("class A { init() { return; } }").is_some());
+}
+
+#[test]
+fn test_invalid_super() {
+    assert!(resolve("class A < A {}").is_none());
+    assert!(resolve("print super.f;").is_none());
+    assert!(resolve("class A { f() { super.f(); } }").is_none());
+    assert!(resolve("class A {} class B < A { f() { super.f(); } }").is_some());
+}
+
+fn resolve(input: &str) -> Option<Vec<Stmt>> {
+    let mut scanner = Scanner::new(input.to_string());
+    let tokens = scanner.scan_tokens().unwrap();
+    let mut statements = Parser::new(tokens.to_vec()).parse().unwrap();
+    Resolver::new().resolve(&mut statements).ok()?;
+    Some(statements)
+}
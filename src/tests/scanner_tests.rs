@@ -1,7 +1,9 @@
 #![cfg(test)]
 
+use crate::error::LoxError;
 use crate::scanner::Scanner;
-use crate::token::{ TokenType, Token };
+use crate::token::{ Span, TokenType, Token };
+use crate::value::Value;
 
 #[test]
 fn test_scan_tokens() {
@@ -11,25 +13,26 @@ fn test_scan_tokens() {
         ".to_string();
     let mut scanner = Scanner::new(source);
     let tokens = scanner.scan_tokens();
-    assert!(tokens.is_some());
+    assert!(tokens.is_ok());
     let tokens = tokens.unwrap();
 
     let tests = vec![
-        Token::new(TokenType::LeftParen, "(".to_string(), 1),
-        Token::new(TokenType::RightParen, ")".to_string(), 1),
-        Token::new(TokenType::LeftBrace, "{".to_string(), 1),
-        Token::new(TokenType::RightBrace, "}".to_string(), 1),
-        Token::new(TokenType::Plus, "+".to_string(), 1),
-        Token::new(TokenType::Minus, "-".to_string(), 1),
-        Token::new(TokenType::Equal, "=".to_string(), 1),
-        Token::new(TokenType::BangEqual, "!=".to_string(), 1),
-        Token::new(TokenType::Bang, "!".to_string(), 1),
-        Token::new(TokenType::Star, "*".to_string(), 1),
-        Token::new(TokenType::Slash, "/".to_string(), 1),
-        Token::new(TokenType::If, "if".to_string(), 2),
-        Token::new(TokenType::Else, "else".to_string(), 2),
-        Token::new(TokenType::Identifier, "ident".to_string(), 2),
-        Token::new(TokenType::EOF, "\0".to_string(), 4),
+        Token::new(TokenType::LeftParen, "(".to_string(), 1).at(1, Span::new(0, 1)),
+        Token::new(TokenType::RightParen, ")".to_string(), 1).at(2, Span::new(1, 2)),
+        Token::new(TokenType::LeftBrace, "{".to_string(), 1).at(3, Span::new(2, 3)),
+        Token::new(TokenType::RightBrace, "}".to_string(), 1).at(4, Span::new(3, 4)),
+        Token::new(TokenType::Plus, "+".to_string(), 1).at(5, Span::new(4, 5)),
+        Token::new(TokenType::Minus, "-".to_string(), 1).at(6, Span::new(5, 6)),
+        Token::new(TokenType::Equal, "=".to_string(), 1).at(7, Span::new(6, 7)),
+        Token::new(TokenType::BangEqual, "!=".to_string(), 1).at(8, Span::new(7, 9)),
+        Token::new(TokenType::Bang, "!".to_string(), 1).at(10, Span::new(9, 10)),
+        Token::new(TokenType::Star, "*".to_string(), 1).at(11, Span::new(10, 11)),
+        Token::new(TokenType::Slash, "/".to_string(), 1).at(12, Span::new(11, 12)),
+        Token::new(TokenType::If, "if".to_string(), 2).at(9, Span::new(21, 23)),
+        Token::new(TokenType::Else, "else".to_string(), 2).at(12, Span::new(24, 28)),
+        Token::new(TokenType::Identifier, "ident".to_string(), 2).at(17, Span::new(29, 34)),
+        Token::new(TokenType::Return, "return".to_string(), 2).at(23, Span::new(35, 41)),
+        Token::new(TokenType::EOF, "\0".to_string(), 4).at(9, Span::new(86, 86)),
     ];
 
     for (i, tt) in tests.iter().enumerate() {
@@ -39,3 +42,74 @@ fn test_scan_tokens() {
         }
     }
 }
+
+#[test]
+fn test_literal_values() {
+    let source = "12.5 \"nil\"".to_string();
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens().unwrap();
+
+    assert_eq!(tokens[0].literal, Some(Value::Number(12.5)));
+    assert_eq!(tokens[1].lexeme, "\"nil\"");
+    assert_eq!(tokens[1].literal, Some(Value::String("nil".to_string())));
+}
+
+#[test]
+fn test_scan_errors() {
+    let source = "var a = 1;\n  @ # \"open".to_string();
+    let mut scanner = Scanner::new(source);
+    let errors = scanner.scan_tokens().unwrap_err();
+
+    assert_eq!(errors, vec![
+        LoxError::Scan { line: 2, column: 3, span: Span::new(13, 14), message: "Unexpected Character.".to_string() },
+        LoxError::Scan { line: 2, column: 5, span: Span::new(15, 16), message: "Unexpected Character.".to_string() },
+        LoxError::Scan { line: 2, column: 7, span: Span::new(17, 22), message: "Unterminated string.".to_string() },
+    ]);
+}
+
+#[test]
+fn test_token_positions() {
+    // Columns count characters; spans count bytes, so the 'é' shifts them apart.
+    // Strings and comments that span lines restart the column count.
+    let source = "print \"é\";\n  x\n\"a\nbc\" /* \n */ y\n".to_string();
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens().unwrap();
+
+    let positions: Vec<(usize, usize, Span)> = tokens.iter()
+        .map(|t| (t.line, t.column, t.span))
+        .collect();
+    assert_eq!(positions, vec![
+        (1, 1, Span::new(0, 5)),
+        (1, 7, Span::new(6, 10)),
+        (1, 10, Span::new(10, 11)),
+        (2, 3, Span::new(14, 15)),
+        (3, 1, Span::new(16, 22)),
+        (5, 5, Span::new(31, 32)),
+        (6, 1, Span::new(33, 33)),
+    ]);
+}
+
+#[test]
+fn test_comments_as_tokens() {
+    let mut scanner = Scanner::with_comments("print 1; // one\n/* two * */ print 2;".to_string());
+    let tokens = scanner.scan_tokens().unwrap();
+    let comments: Vec<(&str, usize)> = tokens.iter()
+        .filter(|token| token.token_type == TokenType::Comment)
+        .map(|token| (token.lexeme.as_str(), token.line))
+        .collect();
+    assert_eq!(comments, vec![("// one", 1), ("/* two * */", 2)]);
+
+    // Without trivia mode they're skipped as before.
+    let mut scanner = Scanner::new("/* two * */ print 2;".to_string());
+    assert_eq!(scanner.scan_tokens().unwrap()[0].token_type, TokenType::Print);
+}
+
+#[test]
+fn test_brackets() {
+    let mut scanner = Scanner::new("xs[0] = [];".to_string());
+    let types: Vec<TokenType> = scanner.scan_tokens().unwrap().iter().map(|token| token.token_type).collect();
+    assert_eq!(types, vec![
+        TokenType::Identifier, TokenType::LeftBracket, TokenType::Number, TokenType::RightBracket,
+        TokenType::Equal, TokenType::LeftBracket, TokenType::RightBracket, TokenType::Semicolon, TokenType::EOF,
+    ]);
+}
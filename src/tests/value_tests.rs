@@ -0,0 +1,52 @@
+#![cfg(test)]
+
+use crate::{map::{LoxMap, MapKey}, value::Value};
+
+#[test]
+fn test_equality() {
+    assert_eq!(Value::Nil, Value::Nil);
+    assert_ne!(Value::String("nil".to_string()), Value::Nil);
+    assert_ne!(Value::String("12".to_string()), Value::Number(12.0));
+    assert_eq!(Value::String("ab".to_string()), Value::String("ab".to_string()));
+    assert_ne!(Value::Bool(false), Value::Nil);
+}
+
+#[test]
+fn test_truthiness() {
+    assert!(!Value::Nil.is_truthy());
+    assert!(!Value::Bool(false).is_truthy());
+    assert!(Value::Number(0.0).is_truthy());
+    assert!(Value::String("".to_string()).is_truthy());
+    assert!(Value::String("false".to_string()).is_truthy());
+}
+
+#[test]
+fn test_display() {
+    assert_eq!(Value::Number(22.0).to_string(), "22");
+    assert_eq!(Value::Number(2.5).to_string(), "2.5");
+    assert_eq!(Value::String("nil".to_string()).to_string(), "nil");
+    assert_eq!(Value::Bool(true).to_string(), "true");
+}
+
+#[test]
+fn test_map_keys() {
+    let key = |value| MapKey::new(&value).unwrap();
+    assert_eq!(key(Value::Number(1.0)), key(Value::Number(1.0)));
+    assert_eq!(key(Value::Number(-0.0)), key(Value::Number(0.0)));
+    assert_ne!(key(Value::Number(1.0)), key(Value::String("1".to_string())));
+    assert!(MapKey::new(&Value::Nil).is_err());
+    assert!(MapKey::new(&Value::Number(f64::NAN)).is_err());
+}
+
+#[test]
+fn test_map_keeps_insertion_order() {
+    let mut map = LoxMap::new();
+    for (i, name) in ["c", "a", "b"].into_iter().enumerate() {
+        map.insert(MapKey::String(name.to_string()), Value::Number(i as f64));
+    }
+    map.insert(MapKey::String("c".to_string()), Value::Nil);
+    assert_eq!(map.remove(&MapKey::String("a".to_string())), Some(Value::Number(1.0)));
+    map.insert(MapKey::String("a".to_string()), Value::Bool(true));
+
+    assert_eq!(Value::map(map).to_string(), "{\"c\": nil, \"b\": 2, \"a\": true}");
+}
@@ -1,7 +1,10 @@
+use crate::value::Value;
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum TokenType {
     // Single Character tokens.
-    LeftParen, RightParen, LeftBrace, RightBrace,
+    LeftParen, RightParen, LeftBrace, RightBrace, LeftBracket, RightBracket,
     Comma, Dot, Minus, Plus, Semicolon, Slash, Star,
     Qmark, Colon,
 
@@ -15,21 +18,60 @@ pub enum TokenType {
     Identifier, String, Number,
 
     // Keywords.
-    And, Else, False, For, If, Nil, Or,
-    Print, True, Var, While,
+    And, Break, Class, Continue, Else, False, For, Fun, If, Nil, Or,
+    Print, Return, Super, This, True, Var, While,
+
+    // Only produced by `Scanner::with_comments`.
+    Comment,
 
     EOF
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+/// A half-open range of byte offsets into the source.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span { start: self.start.min(other.start), end: self.end.max(other.end) }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
+    pub literal: Option<Value>,
     pub line: usize,
+    /// The 1-based column, counted in characters, where the token starts.
+    pub column: usize,
+    pub span: Span,
 }
 
 impl Token {
     pub fn new(token_type: TokenType, lexeme: String, line: usize) -> Self {
-        Token { token_type, lexeme, line }
+        Token { token_type, lexeme, literal: None, line, column: 0, span: Span::default() }
+    }
+
+    pub fn with_literal(
+        token_type: TokenType, lexeme: String,
+        literal: Value, line: usize
+    ) -> Self {
+        Token { token_type, lexeme, literal: Some(literal), line, column: 0, span: Span::default() }
+    }
+
+    /// Places the token at `column` and `span` in the source.
+    pub fn at(mut self, column: usize, span: Span) -> Self {
+        self.column = column;
+        self.span = span;
+        self
     }
 }
@@ -0,0 +1,205 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use crate::{
+    callable::{LoxFunction, NativeFunction},
+    class::{LoxClass, LoxInstance},
+    map::{LoxMap, MapKey},
+    vm::{BoundMethod, Closure},
+};
+
+/// The items of a list, shared by every value that refers to it.
+pub type ListItems = Rc<RefCell<Vec<Value>>>;
+
+/// A runtime value produced by evaluating an expression.
+///
+/// New kinds of values (callables, class instances, ...) get their own
+/// variant here so every stage agrees on what a Lox value is.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    /// Shared and mutable, so every copy sees changes made through another.
+    List(ListItems),
+    /// Shared like a list.
+    Map(Rc<RefCell<LoxMap>>),
+    Function(Rc<LoxFunction>),
+    Native(Rc<NativeFunction>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    Closure(Rc<Closure>),
+    BoundMethod(Rc<BoundMethod>),
+}
+
+impl Value {
+    pub fn list(items: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn map(map: LoxMap) -> Value {
+        Value::Map(Rc::new(RefCell::new(map)))
+    }
+
+    /// `self[index]`, as both backends evaluate it.
+    pub fn get_index(&self, index: &Value) -> Result<Value, String> {
+        match self {
+            Value::List(items) => {
+                let items = items.borrow();
+                let i = Value::list_index(index, items.len())?;
+                Ok(items[i].clone())
+            },
+            Value::Map(map) => {
+                let key = MapKey::new(index)?;
+                map.borrow().get(&key).cloned().ok_or_else(|| format!("Key {} isn't in the map.", key))
+            },
+            _ => Err(INDEX_ERROR.to_string()),
+        }
+    }
+
+    /// `self[index] = value`. Lists can only replace an item; maps add the
+    /// key if it isn't there yet.
+    pub fn set_index(&self, index: &Value, value: Value) -> Result<(), String> {
+        match self {
+            Value::List(items) => {
+                let mut items = items.borrow_mut();
+                let i = Value::list_index(index, items.len())?;
+                items[i] = value;
+                Ok(())
+            },
+            Value::Map(map) => {
+                map.borrow_mut().insert(MapKey::new(index)?, value);
+                Ok(())
+            },
+            _ => Err(INDEX_ERROR.to_string()),
+        }
+    }
+
+    /// Where `index` points in a list of `len` items, or why it doesn't
+    /// point anywhere. Both backends and the list natives share these
+    /// messages.
+    pub fn list_index(index: &Value, len: usize) -> Result<usize, String> {
+        let Value::Number(n) = index else {
+            return Err("List index must be a number.".to_string());
+        };
+        if n.fract() != 0.0 {
+            return Err(format!("List index {} isn't a whole number.", n));
+        }
+        if *n < 0.0 {
+            return Err(format!("List index {} is negative.", n));
+        }
+        if *n >= len as f64 {
+            return Err(format!("List index {} is out of bounds for a list of length {}.", n, len));
+        }
+        Ok(*n as usize)
+    }
+
+    /// `nil` and `false` are falsey, everything else is truthy.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Nil => false,
+            Value::Bool(b) => *b,
+            _ => true,
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Native(a), Value::Native(b)) => Rc::ptr_eq(a, b),
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            (Value::Closure(a), Value::Closure(b)) => Rc::ptr_eq(a, b),
+            (Value::BoundMethod(a), Value::BoundMethod(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", s),
+            // A list or map can hold itself; print the inner copy as `[...]`
+            // or `{...}`.
+            Value::List(items) => guard_cycle(f, Rc::as_ptr(items).cast(), "[...]", |f| {
+                write_items(f, &items.borrow())
+            }),
+            Value::Map(map) => guard_cycle(f, Rc::as_ptr(map).cast(), "{...}", |f| {
+                write_entries(f, &map.borrow())
+            }),
+            Value::Function(fun) => write!(f, "<fn {}>", fun.declaration.name.lexeme),
+            Value::Native(_) => write!(f, "<native fn>"),
+            Value::Class(class) => write!(f, "{}", class.name),
+            Value::Instance(instance) => write!(f, "{} instance", instance.borrow().class.name),
+            Value::Closure(closure) => write!(f, "<fn {}>", closure.function.name),
+            Value::BoundMethod(bound) => write!(f, "<fn {}>", bound.method.function.name),
+        }
+    }
+}
+
+const INDEX_ERROR: &str = "Only lists and maps can be indexed.";
+
+thread_local! {
+    /// The lists and maps whose `Display` is running, innermost last.
+    static PRINTING: RefCell<Vec<*const ()>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `write` unless the value at `ptr` is already being printed further
+/// out, in which case it writes `cycle` instead.
+fn guard_cycle(
+    f: &mut fmt::Formatter<'_>,
+    ptr: *const (),
+    cycle: &str,
+    write: impl FnOnce(&mut fmt::Formatter<'_>) -> fmt::Result
+) -> fmt::Result {
+    if PRINTING.with_borrow(|printing| printing.contains(&ptr)) {
+        return write!(f, "{}", cycle);
+    }
+    PRINTING.with_borrow_mut(|printing| printing.push(ptr));
+    let result = write(f);
+    PRINTING.with_borrow_mut(|printing| printing.pop());
+    result
+}
+
+fn write_items(f: &mut fmt::Formatter<'_>, items: &[Value]) -> fmt::Result {
+    write!(f, "[")?;
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write_item(f, item)?;
+    }
+    write!(f, "]")
+}
+
+fn write_entries(f: &mut fmt::Formatter<'_>, map: &LoxMap) -> fmt::Result {
+    write!(f, "{{")?;
+    for (i, (key, value)) in map.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}: ", key)?;
+        write_item(f, value)?;
+    }
+    write!(f, "}}")
+}
+
+fn write_item(f: &mut fmt::Formatter<'_>, item: &Value) -> fmt::Result {
+    // Quote strings so `["a, b"]` and `["a", "b"]` differ.
+    match item {
+        Value::String(s) => write!(f, "{:?}", s),
+        item => write!(f, "{}", item),
+    }
+}
@@ -0,0 +1,537 @@
+use std::{cell::RefCell, collections::HashMap, fmt, io::{self, Write}, rc::Rc};
+
+use crate::{
+    chunk::{Chunk, Constant, FunctionProto, OpCode},
+    class::{LoxClass, LoxInstance},
+    error::LoxError,
+    map::{LoxMap, MapKey},
+    natives,
+    value::Value,
+};
+
+const FRAMES_MAX: usize = 1024;
+
+/// A variable captured by a closure. It points into the stack while the
+/// variable is still live there, and owns the value once it is closed.
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct Closure {
+    pub function: Rc<FunctionProto>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.function.name)
+    }
+}
+
+/// A method closure together with the instance it was read from.
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<Closure>,
+}
+
+impl fmt::Debug for BoundMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.method.function.name)
+    }
+}
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    /// Index of the frame's slot zero on the value stack.
+    slots: usize,
+}
+
+/// A stack-based virtual machine that runs chunks produced by the compiler.
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<String, Value>,
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    output: Box<dyn Write>,
+    /// Where the instruction being run starts in the current frame's chunk,
+    /// so errors can find its span.
+    instruction: usize,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    /// A virtual machine whose `print` statements write to `output`.
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        let mut globals = HashMap::new();
+        for native in natives::all() {
+            globals.insert(native.name.to_string(), Value::Native(Rc::new(native)));
+        }
+
+        Self {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals,
+            open_upvalues: Vec::new(),
+            output,
+            instruction: 0,
+        }
+    }
+
+    /// The global variables defined so far, natives excluded, sorted by name.
+    pub fn globals(&self) -> Vec<(String, Value)> {
+        let mut globals: Vec<(String, Value)> = self.globals
+            .iter()
+            .filter(|(_, value)| !matches!(value, Value::Native(_)))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        globals.sort_by(|a, b| a.0.cmp(&b.0));
+        globals
+    }
+
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.globals.insert(name.to_string(), value);
+    }
+
+    pub fn interpret(&mut self, function: Rc<FunctionProto>) -> Result<(), LoxError> {
+        self.evaluate(function).map(|_| ())
+    }
+
+    /// Runs `function` like `interpret` and returns the value it returns,
+    /// such as a lone expression compiled for the REPL to echo.
+    pub fn evaluate(&mut self, function: Rc<FunctionProto>) -> Result<Value, LoxError> {
+        let closure = Rc::new(Closure { function, upvalues: Vec::new() });
+        self.stack.push(Value::Closure(Rc::clone(&closure)));
+
+        let result = self.call(closure, 0).and_then(|_| self.run());
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result
+    }
+
+    fn run(&mut self) -> Result<Value, LoxError> {
+        loop {
+            let closure = Rc::clone(&self.frames.last().unwrap().closure);
+            let chunk = &closure.function.chunk;
+
+            self.instruction = self.frames.last().unwrap().ip;
+            let Some(op) = OpCode::from_byte(self.read_byte(chunk)) else {
+                return self.runtime_error("Unknown opcode.");
+            };
+
+            match op {
+                OpCode::Constant => {
+                    let value = match &chunk.constants[self.read_u16(chunk) as usize] {
+                        Constant::Number(n) => Value::Number(*n),
+                        Constant::String(s) => Value::String(s.clone()),
+                        Constant::Function(_) => return self.runtime_error("Functions must be loaded as closures."),
+                    };
+                    self.stack.push(value);
+                },
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Bool(true)),
+                OpCode::False => self.stack.push(Value::Bool(false)),
+                OpCode::Pop => { self.pop(); },
+                OpCode::GetLocal => {
+                    let slot = self.slot(chunk);
+                    self.stack.push(self.stack[slot].clone());
+                },
+                OpCode::SetLocal => {
+                    let slot = self.slot(chunk);
+                    self.stack[slot] = self.peek(0).clone();
+                },
+                OpCode::GetGlobal => {
+                    let name = self.read_string(chunk);
+                    match self.globals.get(name) {
+                        Some(value) => self.stack.push(value.clone()),
+                        None => return self.runtime_error(&format!("Undefined Variable '{}'.", name)),
+                    }
+                },
+                OpCode::DefineGlobal => {
+                    let name = self.read_string(chunk).to_string();
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                },
+                OpCode::SetGlobal => {
+                    let name = self.read_string(chunk);
+                    let value = self.peek(0).clone();
+                    match self.globals.get_mut(name) {
+                        Some(slot) => *slot = value,
+                        None => return self.runtime_error(&format!("Undefined Variable '{}'.", name)),
+                    }
+                },
+                OpCode::GetUpvalue => {
+                    let index = self.read_byte(chunk) as usize;
+                    let value = match &*closure.upvalues[index].borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                },
+                OpCode::SetUpvalue => {
+                    let index = self.read_byte(chunk) as usize;
+                    let value = self.peek(0).clone();
+                    let mut upvalue = closure.upvalues[index].borrow_mut();
+                    match &mut *upvalue {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                },
+                OpCode::GetProperty => {
+                    let name = self.read_string(chunk);
+                    let Value::Instance(instance) = self.peek(0).clone() else {
+                        return self.runtime_error("Only instances have properties.");
+                    };
+
+                    if let Some(value) = instance.borrow().field(name) {
+                        self.pop();
+                        self.stack.push(value);
+                        continue;
+                    }
+
+                    let class = Rc::clone(&instance.borrow().class);
+                    self.bind_method(&class, name)?;
+                },
+                OpCode::SetProperty => {
+                    let name = self.read_string(chunk);
+                    let Value::Instance(instance) = self.peek(1).clone() else {
+                        return self.runtime_error("Only instances have fields.");
+                    };
+
+                    let value = self.pop();
+                    instance.borrow_mut().set(name, value.clone());
+                    self.pop();
+                    self.stack.push(value);
+                },
+                OpCode::GetSuper => {
+                    let name = self.read_string(chunk);
+                    let Value::Class(superclass) = self.pop() else {
+                        return self.runtime_error("Superclass must be a class.");
+                    };
+                    self.bind_method(&superclass, name)?;
+                },
+                OpCode::Equal => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.stack.push(Value::Bool(a == b));
+                },
+                OpCode::Greater | OpCode::Less | OpCode::Subtract | OpCode::Multiply | OpCode::Divide => {
+                    let (Value::Number(a), Value::Number(b)) = (self.peek(1), self.peek(0)) else {
+                        return self.runtime_error("Operands must be number.");
+                    };
+                    let (a, b) = (*a, *b);
+                    self.pop();
+                    self.pop();
+
+                    self.stack.push(match op {
+                        OpCode::Greater => Value::Bool(a > b),
+                        OpCode::Less => Value::Bool(a < b),
+                        OpCode::Subtract => Value::Number(a - b),
+                        OpCode::Multiply => Value::Number(a * b),
+                        _ => Value::Number(a / b),
+                    });
+                },
+                OpCode::Add => {
+                    let result = match (self.peek(1), self.peek(0)) {
+                        (Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+                        (a @ Value::String(_), b) | (a, b @ Value::String(_)) => {
+                            Value::String(format!("{}{}", a, b))
+                        },
+                        _ => return self.runtime_error("Operands must be two numbers or two strings."),
+                    };
+                    self.pop();
+                    self.pop();
+                    self.stack.push(result);
+                },
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.stack.push(Value::Bool(!value.is_truthy()));
+                },
+                OpCode::Negate => {
+                    let Value::Number(n) = self.peek(0) else {
+                        return self.runtime_error("Operand must be a number.");
+                    };
+                    let n = *n;
+                    self.pop();
+                    self.stack.push(Value::Number(-n));
+                },
+                OpCode::Print => {
+                    let value = self.pop();
+                    if let Err(error) = writeln!(self.output, "{}", value) {
+                        return self.runtime_error(&format!("Could not write output: {}.", error));
+                    }
+                },
+                OpCode::Jump => {
+                    let offset = self.read_u16(chunk) as usize;
+                    self.frames.last_mut().unwrap().ip += offset;
+                },
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16(chunk) as usize;
+                    if !self.peek(0).is_truthy() {
+                        self.frames.last_mut().unwrap().ip += offset;
+                    }
+                },
+                OpCode::Loop => {
+                    let offset = self.read_u16(chunk) as usize;
+                    self.frames.last_mut().unwrap().ip -= offset;
+                },
+                OpCode::Call => {
+                    let arg_count = self.read_byte(chunk) as usize;
+                    let callee = self.peek(arg_count).clone();
+                    self.call_value(callee, arg_count)?;
+                },
+                OpCode::Closure => {
+                    let Constant::Function(function) = &chunk.constants[self.read_u16(chunk) as usize] else {
+                        return self.runtime_error("Expect a function constant.");
+                    };
+
+                    let slots = self.frames.last().unwrap().slots;
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                    for _ in 0..function.upvalue_count {
+                        let is_local = self.read_byte(chunk) == 1;
+                        let index = self.read_byte(chunk) as usize;
+                        if is_local {
+                            upvalues.push(self.capture_upvalue(slots + index));
+                        } else {
+                            upvalues.push(Rc::clone(&closure.upvalues[index]));
+                        }
+                    }
+
+                    let function = Rc::clone(function);
+                    self.stack.push(Value::Closure(Rc::new(Closure { function, upvalues })));
+                },
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                },
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    self.close_upvalues(frame.slots);
+                    self.stack.truncate(frame.slots);
+
+                    if self.frames.is_empty() {
+                        return Ok(result);
+                    }
+                    self.stack.push(result);
+                },
+                OpCode::BuildList => {
+                    let count = self.read_u16(chunk) as usize;
+                    let items = self.stack.split_off(self.stack.len().saturating_sub(count));
+                    self.stack.push(Value::list(items));
+                },
+                OpCode::BuildMap => {
+                    let count = self.read_u16(chunk) as usize;
+                    let pairs = self.stack.split_off(self.stack.len().saturating_sub(count * 2));
+                    let mut map = LoxMap::new();
+                    for pair in pairs.chunks(2) {
+                        match MapKey::new(&pair[0]) {
+                            Ok(key) => map.insert(key, pair[1].clone()),
+                            Err(message) => return self.runtime_error(&message),
+                        }
+                    }
+                    self.stack.push(Value::map(map));
+                },
+                OpCode::GetIndex => {
+                    let item = match self.peek(1).get_index(self.peek(0)) {
+                        Ok(item) => item,
+                        Err(message) => return self.runtime_error(&message),
+                    };
+                    self.pop();
+                    self.pop();
+                    self.stack.push(item);
+                },
+                OpCode::SetIndex => {
+                    let value = self.pop();
+                    if let Err(message) = self.peek(1).set_index(self.peek(0), value.clone()) {
+                        return self.runtime_error(&message);
+                    }
+                    self.pop();
+                    self.pop();
+                    self.stack.push(value);
+                },
+                OpCode::Class => {
+                    let name = self.read_string(chunk).to_string();
+                    let method_count = self.read_byte(chunk) as usize;
+                    let has_superclass = self.read_byte(chunk) == 1;
+
+                    let mut methods = HashMap::new();
+                    for method in self.stack.split_off(self.stack.len() - method_count) {
+                        if let Value::Closure(closure) = &method {
+                            methods.insert(closure.function.name.clone(), method);
+                        }
+                    }
+
+                    let mut superclass = None;
+                    if has_superclass {
+                        let Value::Class(parent) = self.peek(0) else {
+                            return self.runtime_error("Superclass must be a class.");
+                        };
+                        superclass = Some(Rc::clone(parent));
+                    }
+
+                    let class = LoxClass::new(name, superclass, methods);
+                    self.stack.push(Value::Class(Rc::new(class)));
+                },
+            }
+        }
+    }
+
+    fn call_value(&mut self, callee: Value, arg_count: usize) -> Result<(), LoxError> {
+        match callee {
+            Value::Closure(closure) => self.call(closure, arg_count),
+            Value::BoundMethod(bound) => {
+                let slot = self.stack.len() - arg_count - 1;
+                self.stack[slot] = bound.receiver.clone();
+                self.call(Rc::clone(&bound.method), arg_count)
+            },
+            Value::Class(class) => {
+                let slot = self.stack.len() - arg_count - 1;
+                let instance = LoxInstance::new(Rc::clone(&class));
+                self.stack[slot] = Value::Instance(Rc::new(RefCell::new(instance)));
+
+                match class.find_method("init") {
+                    Some(Value::Closure(initializer)) => self.call(initializer, arg_count),
+                    _ if arg_count != 0 => {
+                        self.operand_error(&format!("Expected 0 arguments but got {}.", arg_count))
+                    },
+                    _ => Ok(()),
+                }
+            },
+            Value::Native(native) => {
+                if arg_count != native.arity {
+                    return self.operand_error(
+                        &format!("Expected {} arguments but got {}.", native.arity, arg_count)
+                    );
+                }
+
+                let args = self.stack.split_off(self.stack.len() - arg_count);
+                match (native.function)(&args) {
+                    Ok(result) => {
+                        self.pop();
+                        self.stack.push(result);
+                        Ok(())
+                    },
+                    Err(msg) => self.operand_error(&msg),
+                }
+            },
+            _ => self.runtime_error("Can only call functions and classes."),
+        }
+    }
+
+    fn call(&mut self, closure: Rc<Closure>, arg_count: usize) -> Result<(), LoxError> {
+        if arg_count != closure.function.arity {
+            return self.operand_error(
+                &format!("Expected {} arguments but got {}.", closure.function.arity, arg_count)
+            );
+        }
+
+        if self.frames.len() == FRAMES_MAX {
+            return self.operand_error("Stack overflow.");
+        }
+
+        let slots = self.stack.len() - arg_count - 1;
+        self.frames.push(CallFrame { closure, ip: 0, slots });
+        Ok(())
+    }
+
+    /// Replaces the instance on top of the stack with its method `name`.
+    fn bind_method(&mut self, class: &LoxClass, name: &str) -> Result<(), LoxError> {
+        let Some(Value::Closure(method)) = class.find_method(name) else {
+            return self.operand_error(&format!("Undefined property '{}'.", name));
+        };
+
+        let receiver = self.pop();
+        let bound = BoundMethod { receiver, method };
+        self.stack.push(Value::BoundMethod(Rc::new(bound)));
+        Ok(())
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        for upvalue in &self.open_upvalues {
+            if let Upvalue::Open(open_slot) = *upvalue.borrow() {
+                if open_slot == slot {
+                    return Rc::clone(upvalue);
+                }
+            }
+        }
+
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(Rc::clone(&upvalue));
+        upvalue
+    }
+
+    /// Moves every captured variable at or above `last` off the stack.
+    fn close_upvalues(&mut self, last: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let mut upvalue = upvalue.borrow_mut();
+            match *upvalue {
+                Upvalue::Open(slot) if slot >= last => {
+                    *upvalue = Upvalue::Closed(stack[slot].clone());
+                    false
+                },
+                _ => true,
+            }
+        });
+    }
+
+    fn read_byte(&mut self, chunk: &Chunk) -> u8 {
+        let frame = self.frames.last_mut().unwrap();
+        frame.ip += 1;
+        chunk.code[frame.ip - 1]
+    }
+
+    fn read_u16(&mut self, chunk: &Chunk) -> u16 {
+        let frame = self.frames.last_mut().unwrap();
+        frame.ip += 2;
+        chunk.read_u16(frame.ip - 2)
+    }
+
+    fn read_string<'a>(&mut self, chunk: &'a Chunk) -> &'a str {
+        match &chunk.constants[self.read_u16(chunk) as usize] {
+            Constant::String(s) => s,
+            _ => "",
+        }
+    }
+
+    /// Reads a local slot operand and turns it into a stack index.
+    fn slot(&mut self, chunk: &Chunk) -> usize {
+        let slot = self.read_byte(chunk) as usize;
+        self.frames.last().unwrap().slots + slot
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap_or(Value::Nil)
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    /// An error at the span of the instruction being run.
+    fn runtime_error<T>(&self, msg: &str) -> Result<T, LoxError> {
+        self.error_at(self.instruction, msg)
+    }
+
+    /// An error at the span of the instruction's first operand, for the
+    /// instructions that point some errors at part of their expression.
+    fn operand_error<T>(&self, msg: &str) -> Result<T, LoxError> {
+        self.error_at(self.instruction + 1, msg)
+    }
+
+    fn error_at<T>(&self, offset: usize, msg: &str) -> Result<T, LoxError> {
+        let chunk = &self.frames.last().unwrap().closure.function.chunk;
+        let line = chunk.lines[offset.min(chunk.lines.len() - 1)];
+        let span = chunk.spans.get(offset).copied();
+        Err(LoxError::Runtime { line, token: None, span, message: msg.to_string() })
+    }
+}